@@ -1,4 +1,5 @@
 import torch
+from safetensors.torch import save_file
 from torch.nn.modules.module import T
 
 device_name = 'cuda' if torch.cuda.is_available() else 'mps'
@@ -14,6 +15,8 @@ class Embedding(torch.nn.Module):
 
 embedding = Embedding()
 embedding.lay_1.weight.data = data['tok_embeddings.weight'].data.clone().detach().to(dtype=torch.float32).T
+# Plain matrix for the Rust StaticEmbedding, which loads it without libtorch
+save_file({'tok_embeddings.weight': data['tok_embeddings.weight'].contiguous()}, './old_backend/tok_embeddings.safetensors')
 del data
 # Create a sample input tensor
 sample_input = torch.randn(1, 128256).to(dtype=torch.float32)
@@ -5,16 +5,19 @@
 
 import numpy as np
 cimport numpy as np
-from libcpp.string cimport string
-from libcpp.vector cimport vector
+from libc.stdlib cimport malloc, free
+from libc.string cimport memcpy
 
 from llamafile_embedding_interface import LlamafileEmbeddingInterface, init
 
 cdef class PyLlamafileEmbedding:
     cdef LlamafileEmbeddingInterface _interface
+    cdef public size_t dims
 
     def __cinit__(self, model_path: str):
         self._interface = init(model_path)
+        # llamafile can't report its width, so embed something once to find it.
+        self.dims = len(self._interface.get_embedding("dims"))
 
     def get_embedding(self, text: str) -> np.ndarray:
         return self._interface.get_embedding(text)
@@ -22,6 +25,18 @@ cdef class PyLlamafileEmbedding:
     def get_embeddings(self, texts: list) -> np.ndarray:
         return self._interface.get_embeddings(texts)
 
+# Copies an embedding into a malloc'd buffer the caller releases with
+# free_embedding. The caller reads exactly `expected` floats, so anything
+# else (a short or oddly sized result) is NULL, as is running out of memory.
+cdef float* copy_out(array, size_t expected):
+    cdef np.ndarray[np.float32_t, ndim=1] flat = np.ascontiguousarray(array, dtype=np.float32).ravel()
+    if expected == 0 or <size_t> flat.shape[0] != expected:
+        return NULL
+    cdef float* buffer = <float*> malloc(flat.shape[0] * sizeof(float))
+    if buffer != NULL:
+        memcpy(buffer, &flat[0], flat.shape[0] * sizeof(float))
+    return buffer
+
 # C API
 cdef extern from *:
     ctypedef char* const_char_ptr "const char*"
@@ -33,8 +48,25 @@ cdef public api:
     void destroy_embedding(PyLlamafileEmbedding* embedding):
         del embedding
 
-    np.ndarray[np.float32_t, ndim=1] get_single_embedding(PyLlamafileEmbedding* embedding, const_char_ptr text):
-        return embedding.get_embedding(text.decode('utf-8'))
+    size_t get_embedding_dims(PyLlamafileEmbedding* embedding):
+        return embedding.dims
+
+    # dims floats, or NULL on failure.
+    float* get_single_embedding(PyLlamafileEmbedding* embedding, const_char_ptr text):
+        try:
+            return copy_out(embedding.get_embedding(text.decode('utf-8')), embedding.dims)
+        except Exception:
+            return NULL
+
+    # num_texts * dims floats, row per text, or NULL on failure.
+    float* get_multiple_embeddings(PyLlamafileEmbedding* embedding, const_char_ptr* texts, size_t num_texts):
+        try:
+            return copy_out(
+                embedding.get_embeddings([texts[i].decode('utf-8') for i in range(num_texts)]),
+                num_texts * embedding.dims,
+            )
+        except Exception:
+            return NULL
 
-    np.ndarray[np.float32_t, ndim=2] get_multiple_embeddings(PyLlamafileEmbedding* embedding, vector[string] texts):
-        return embedding.get_embeddings([text.decode('utf-8') for text in texts])
\ No newline at end of file
+    void free_embedding(float* buffer):
+        free(buffer)
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use crate::helpers::{insert_top_k, tokenize};
+use crate::types::Fusion;
+
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// Inverted index over the text each record was inserted with, scored with
+/// Okapi BM25. Ids line up with the record ids in `VectorDB`.
+#[derive(Default)]
+pub(crate) struct Bm25Index {
+    /// token -> (record id -> term frequency)
+    postings: HashMap<String, HashMap<usize, u32>>,
+    /// Token count per record, 0 for records with no text or that were removed.
+    doc_lens: Vec<u32>,
+    /// Distinct tokens per record, so removal knows which postings to touch.
+    doc_terms: Vec<Vec<String>>,
+    live_docs: usize,
+    total_len: u64,
+}
+
+impl Bm25Index {
+    pub fn add(&mut self, id: usize, text: &str) {
+        let tokens = tokenize(text);
+        if self.doc_lens.len() <= id {
+            self.doc_lens.resize(id + 1, 0);
+            self.doc_terms.resize(id + 1, vec![]);
+        }
+        if tokens.is_empty() {
+            return;
+        }
+        for token in &tokens {
+            *self.postings.entry(token.clone()).or_default().entry(id).or_default() += 1;
+        }
+        let mut terms = tokens.clone();
+        terms.sort();
+        terms.dedup();
+        self.doc_terms[id] = terms;
+        self.doc_lens[id] = tokens.len() as u32;
+        self.live_docs += 1;
+        self.total_len += tokens.len() as u64;
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        let len = match self.doc_lens.get_mut(id) {
+            Some(len) if *len > 0 => std::mem::take(len),
+            _ => return,
+        };
+        for token in std::mem::take(&mut self.doc_terms[id]) {
+            if let Some(ids) = self.postings.get_mut(&token) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.postings.remove(&token);
+                }
+            }
+        }
+        self.live_docs -= 1;
+        self.total_len -= len as u64;
+    }
+
+    /// Best `k` records for `query` among those `keep` accepts, highest first.
+    pub fn search(&self, query: &str, k: usize, keep: impl Fn(usize) -> bool) -> Vec<(usize, f32)> {
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        let avg_len = self.total_len as f32 / self.live_docs.max(1) as f32;
+        let mut tokens = tokenize(query);
+        tokens.sort();
+        tokens.dedup();
+        for token in tokens {
+            let ids = match self.postings.get(&token) {
+                Some(ids) => ids,
+                None => continue,
+            };
+            let df = ids.len() as f32;
+            let idf = (1.0 + (self.live_docs as f32 - df + 0.5) / (df + 0.5)).ln();
+            for (id, tf) in ids {
+                let tf = *tf as f32;
+                let norm = K1 * (1.0 - B + B * self.doc_lens[*id] as f32 / avg_len);
+                *scores.entry(*id).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+        let mut index_vec = vec![];
+        let mut dist_vec = vec![];
+        for (id, score) in scores {
+            if keep(id) {
+                insert_top_k(&mut index_vec, &mut dist_vec, id, score, k);
+            }
+        }
+        index_vec.into_iter().zip(dist_vec).collect()
+    }
+}
+
+/// RRF score for a 1-based `rank` in one result list.
+fn rrf(rank: usize, k: f32) -> f32 {
+    1.0 / (k + rank as f32)
+}
+
+/// Rescales scores to 0..=1 within one result list; a list with a single
+/// distinct score maps everything to 1.
+fn min_max(hits: &[(usize, f32)]) -> HashMap<usize, f32> {
+    let min = hits.iter().map(|(_, x)| *x).fold(f32::INFINITY, f32::min);
+    let max = hits.iter().map(|(_, x)| *x).fold(f32::NEG_INFINITY, f32::max);
+    hits.iter()
+        .map(|(id, x)| (*id, if max > min { (x - min) / (max - min) } else { 1.0 }))
+        .collect()
+}
+
+/// Merges a dense result list with a keyword or sparse one into one
+/// ranking, best first.
+pub(crate) fn fuse(dense: &[(usize, f32)], other: &[(usize, f32)], fusion: &Fusion) -> Vec<(usize, f32)> {
+    let mut fused: HashMap<usize, f32> = HashMap::new();
+    match fusion {
+        Fusion::Rrf { k } => {
+            for hits in [dense, other] {
+                for (rank, (id, _)) in hits.iter().enumerate() {
+                    *fused.entry(*id).or_default() += rrf(rank + 1, *k);
+                }
+            }
+        }
+        Fusion::Weighted { dense_weight } => {
+            for (id, x) in min_max(dense) {
+                *fused.entry(id).or_default() += dense_weight * x;
+            }
+            for (id, x) in min_max(other) {
+                *fused.entry(id).or_default() += (1.0 - dense_weight) * x;
+            }
+        }
+    }
+    let mut fused: Vec<(usize, f32)> = fused.into_iter().collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    fused
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use crate::helpers::sentence_spans;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum ChunkUnit {
+    Chars,
+    Sentences,
+    /// Alphanumeric runs, the same tokens the keyword indexes use.
+    Tokens,
+}
+
+/// Splits long text into windows of `size` units, each sharing `overlap`
+/// units with the one before it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ChunkOptions {
+    pub unit: ChunkUnit,
+    pub size: usize,
+    #[serde(default)]
+    pub overlap: usize,
+}
+
+/// Where a chunk record came from: the record id of its document's first
+/// chunk, which stands in as the document id, and its byte range there.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ChunkInfo {
+    pub parent: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Byte ranges of the chunks of `text`, in order.
+pub(crate) fn chunk_spans(text: &str, options: &ChunkOptions) -> Result<Vec<(usize, usize)>, String> {
+    if options.size == 0 || options.overlap >= options.size {
+        return Err("Chunk size must be at least 1 and larger than the overlap".to_string());
+    }
+    let units: Vec<(usize, usize)> = match options.unit {
+        ChunkUnit::Chars => text.char_indices().map(|(i, c)| (i, i + c.len_utf8())).collect(),
+        ChunkUnit::Sentences => sentence_spans(text),
+        ChunkUnit::Tokens => token_spans(text),
+    };
+    let step = options.size - options.overlap;
+    let mut spans = vec![];
+    let mut first = 0;
+    while first < units.len() {
+        let last = first.saturating_add(options.size).min(units.len()) - 1;
+        spans.push((units[first].0, units[last].1));
+        if last == units.len() - 1 {
+            break;
+        }
+        first += step;
+    }
+    Ok(spans)
+}
+
+fn token_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(text: &str, unit: ChunkUnit, size: usize, overlap: usize) -> Vec<&str> {
+        chunk_spans(text, &ChunkOptions { unit, size, overlap })
+            .unwrap()
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect()
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        let options = |size, overlap| ChunkOptions { unit: ChunkUnit::Chars, size, overlap };
+        assert!(chunk_spans("abc", &options(0, 0)).is_err());
+        assert!(chunk_spans("abc", &options(2, 2)).is_err());
+        assert!(chunk_spans("abc", &options(2, 3)).is_err());
+    }
+
+    #[test]
+    fn char_chunks_end_on_the_last_unit() {
+        assert_eq!(chunks("abcdef", ChunkUnit::Chars, 2, 0), ["ab", "cd", "ef"]);
+        assert_eq!(chunks("abcdefg", ChunkUnit::Chars, 3, 0), ["abc", "def", "g"]);
+        // Text that fits in one chunk is one chunk, and empty text is none.
+        assert_eq!(chunks("ab", ChunkUnit::Chars, 5, 1), ["ab"]);
+        assert!(chunks("", ChunkUnit::Chars, 3, 0).is_empty());
+        assert_eq!(chunks("ab", ChunkUnit::Chars, usize::MAX, 0), ["ab"]);
+    }
+
+    #[test]
+    fn overlapping_chunks_share_units_without_a_redundant_tail() {
+        assert_eq!(chunks("abcdefg", ChunkUnit::Chars, 3, 1), ["abc", "cde", "efg"]);
+        // The window reaching the end is the last one, even with overlap left over.
+        assert_eq!(chunks("abcdef", ChunkUnit::Chars, 4, 2), ["abcd", "cdef"]);
+        assert_eq!(chunks("abcde", ChunkUnit::Chars, 3, 2), ["abc", "bcd", "cde"]);
+    }
+
+    #[test]
+    fn char_chunks_respect_utf8_boundaries() {
+        assert_eq!(chunks("héllo", ChunkUnit::Chars, 2, 0), ["hé", "ll", "o"]);
+    }
+
+    #[test]
+    fn token_chunks_span_the_text_between_tokens() {
+        assert_eq!(
+            chunks("one, two three! four", ChunkUnit::Tokens, 2, 1),
+            ["one, two", "two three", "three! four"]
+        );
+    }
+
+    #[test]
+    fn sentence_chunks() {
+        assert_eq!(
+            chunks("First one. Second one! Third?", ChunkUnit::Sentences, 2, 1),
+            ["First one. Second one!", "Second one! Third?"]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use crate::instructions::InstructionTemplates;
+use crate::types::DistanceMetric;
+
+/// What `GET /collection` reports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CollectionInfo {
+    pub config: CollectionConfig,
+    pub records: usize,
+    /// Progress of the running re-embedding job, None if none is running.
+    pub reembedding: Option<ReembedProgress>,
+    /// How the most recent re-embedding job ended, None if none has.
+    pub last_reembed: Option<ReembedOutcome>,
+    pub instructions: InstructionTemplates,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ReembedProgress {
+    pub embedded: usize,
+    /// Records in the snapshot; later inserts are embedded at swap-in.
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum ReembedOutcome {
+    /// Swapped in; `records` were re-embedded.
+    Finished { records: usize },
+    /// The old vectors were kept.
+    Failed(String),
+}
+
+/// Settings fixed when a collection is created; every insert and query is
+/// checked against them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CollectionConfig {
+    pub dims: usize,
+    pub metric: DistanceMetric,
+    /// `Embedder::model_id` of whatever produced the stored vectors; None
+    /// for spaces that only ever take precomputed vectors.
+    pub model_id: Option<String>,
+}
+
+impl CollectionConfig {
+    pub fn check_dims(&self, vector: &[f32]) -> Result<(), String> {
+        if vector.len() != self.dims {
+            return Err(format!("Expected a {} dimension vector, got {}", self.dims, vector.len()));
+        }
+        Ok(())
+    }
+
+    /// Refuses vectors that say they came from a model other than the one
+    /// the collection was built with. Vectors that don't say are taken on
+    /// trust; `model_id` is in `GET /collection` for clients that want the check.
+    pub fn check_model(&self, model_id: Option<&str>) -> Result<(), String> {
+        match (self.model_id.as_deref(), model_id) {
+            (Some(expected), Some(got)) if expected != got => Err(format!(
+                "Collection holds vectors from {} but this one came from {}",
+                expected, got
+            )),
+            _ => Ok(()),
+        }
+    }
+}
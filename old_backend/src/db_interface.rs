@@ -1,78 +1,605 @@
-use crate::vector_db::VectorDB;
+use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use crate::collection::CollectionConfig;
+use crate::dedupe::DedupePolicy;
+use crate::embedder::{Embedder, EmbedderSpec};
+use crate::embedding_cache::CacheConfigRequest;
+use crate::reembed::{self, ReembedOutput, ReembedRequest};
+use crate::llama_embedding::LlamafileEmbedding;
+use crate::vector_db::{Stored, VectorDB};
+use crate::vector_space::VectorSpace;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
-use crate::db_interface::DbCalls::{FetchIndexData, FindIndexes, Insert, Null};
-use crate::types::{InsertRequest, Response};
+use crate::db_interface::DbCalls::{BatchInsert, BatchSearch, CollectionInfo, FinishReembed, Reembed, ConfigureEmbeddingCache, CreatePayloadIndex, CreateVectorSpace, EmbeddingCacheStats, Delete, Explain, FindByPayload, GroupSearch, IngestBatch, Insert, InsertMultiVector, InsertVector, Kill, Null, RangeSearch, Recommend, Search, SearchMultiVector, SearchSparse, SearchVector, SetDedupePolicy, SetInstructions, SetNamedVector, SetTextPipeline, UpdatePayload, UpdateSparse};
+use crate::ingest::IngestLine;
+use crate::payload::{Filter, Payload};
+use crate::payload_index::PayloadIndexType;
+use crate::instructions::InstructionTemplates;
+use crate::text_pipeline::TextPipeline;
+use crate::types::{BatchInsertRequest, BatchSearchRequest, CreateIndexRequest, CreateVectorSpaceRequest, DeleteRequest, FilterRequest, GroupSearchRequest, InsertMultiVectorRequest, InsertRequest, InsertResult, InsertVectorRequest, MmrOptions, MultiVectorSearchRequest, RangeSearchRequest, RecommendRequest, Response, SearchRequest, SetNamedVectorRequest, SparseSearchRequest, UpdatePayloadRequest, UpdateSparseRequest, VectorSearchRequest};
 
 const NUM_INDEXES: usize = 10;
+/// Hits per Response::Hits message when streaming a range search.
+const RANGE_CHUNK_SIZE: usize = 64;
 
 pub enum DbCalls {
-    Insert(String, oneshot::Sender<Response>),
-    FindIndexes(String, oneshot::Sender<Response>),
-    FetchIndexData(Vec<usize>, oneshot::Sender<Response>),
+    Insert(InsertRequest, oneshot::Sender<Response>),
+    BatchInsert(Vec<InsertRequest>, oneshot::Sender<Response>),
+    IngestBatch(Vec<IngestLine>, oneshot::Sender<Response>),
+    UpdatePayload(usize, Payload, oneshot::Sender<Response>),
+    Delete(usize, oneshot::Sender<Response>),
+    CreatePayloadIndex(String, PayloadIndexType, oneshot::Sender<Response>),
+    FindByPayload(Filter, oneshot::Sender<Response>),
+    Explain(Option<Filter>, oneshot::Sender<Response>),
+    Search(SearchRequest, oneshot::Sender<Response>),
+    BatchSearch(BatchSearchRequest, oneshot::Sender<Response>),
+    Recommend(RecommendRequest, oneshot::Sender<Response>),
+    GroupSearch(GroupSearchRequest, oneshot::Sender<Response>),
+    /// Answers with one or more Response::Hits chunks; the stream ends when
+    /// the sender is dropped.
+    RangeSearch(RangeSearchRequest, mpsc::UnboundedSender<Response>),
+    InsertVector(InsertVectorRequest, oneshot::Sender<Response>),
+    SearchVector(VectorSearchRequest, oneshot::Sender<Response>),
+    UpdateSparse(UpdateSparseRequest, oneshot::Sender<Response>),
+    SearchSparse(SparseSearchRequest, oneshot::Sender<Response>),
+    InsertMultiVector(InsertMultiVectorRequest, oneshot::Sender<Response>),
+    SearchMultiVector(MultiVectorSearchRequest, oneshot::Sender<Response>),
+    CreateVectorSpace(CreateVectorSpaceRequest, oneshot::Sender<Response>),
+    SetNamedVector(SetNamedVectorRequest, oneshot::Sender<Response>),
+    SetTextPipeline(TextPipeline, oneshot::Sender<Response>),
+    SetInstructions(InstructionTemplates, oneshot::Sender<Response>),
+    SetDedupePolicy(DedupePolicy, oneshot::Sender<Response>),
+    ConfigureEmbeddingCache(CacheConfigRequest, oneshot::Sender<Response>),
+    EmbeddingCacheStats(oneshot::Sender<Response>),
+    CollectionInfo(oneshot::Sender<Response>),
+    Reembed(ReembedRequest, oneshot::Sender<Response>),
+    /// Sent by the re-embedding job itself when it is done.
+    FinishReembed(Result<ReembedOutput, String>),
     Kill,
     Null,
 }
 
-pub fn db_interface(llamafile_path: &str, dims: usize) -> (JoinHandle<()>, Sender<DbCalls>) {
+pub fn db_interface(llamafile_path: &str) -> Result<(JoinHandle<()>, Sender<DbCalls>), String> {
     let (tx, mut rx): (Sender<DbCalls>, Receiver<DbCalls>) = mpsc::channel(10);
+    let mut vector_db: VectorDB<String> = VectorDB::new(llamafile_path)?;
+    // Weak so a running re-embed job doesn't keep the channel open on shutdown.
+    let job_address = tx.downgrade();
 
     let db_process: JoinHandle<()> = tokio::spawn(async move {
-        let mut vector_db: VectorDB<String> = VectorDB::new(llamafile_path, dims);
         loop {
             match rx.recv().await.unwrap_or(Null) {
-                Insert(x, return_sender) => {
-                    vector_db.insert(x.clone(), x);
+                Insert(request, return_sender) => {
+                    let result = match &request.chunk {
+                        Some(chunk) => vector_db.insert_chunked(&request.entry, request.payload, chunk).map(Stored::New),
+                        None => vector_db.insert(request.entry.clone(), request.entry, request.payload),
+                    };
+                    return_sender.send(inserted(result)).unwrap();
+                }
+                BatchInsert(entries, return_sender) => {
+                    let lines = entries
+                        .into_iter()
+                        .map(|entry| IngestLine {
+                            entry: entry.entry,
+                            vector: None,
+                            payload: entry.payload,
+                            sparse: None,
+                            named: HashMap::new(),
+                            chunk: entry.chunk,
+                            model: None,
+                        })
+                        .collect();
+                    let results = ingest_batch(&mut vector_db, lines);
+                    return_sender.send(Response::InsertResults(results)).unwrap();
+                }
+                IngestBatch(lines, return_sender) => {
+                    let results = ingest_batch(&mut vector_db, lines);
+                    return_sender.send(Response::InsertResults(results)).unwrap();
+                }
+                UpdatePayload(id, payload, return_sender) => {
+                    let response = if vector_db.update_payload(id, payload) {
+                        Response::Success
+                    } else {
+                        Response::Error("Record not found".to_string())
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                Delete(id, return_sender) => {
+                    let response = if vector_db.delete(id) {
+                        Response::Success
+                    } else {
+                        Response::Error("Record not found".to_string())
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                CreatePayloadIndex(key, index_type, return_sender) => {
+                    vector_db.create_payload_index(key, index_type);
                     return_sender.send(Response::Success).unwrap();
                 }
-                FindIndexes(x, return_sender) => {
-                    let indexes = vector_db.get_top_k_indexes(x, NUM_INDEXES);
+                FindByPayload(filter, return_sender) => {
+                    let indexes = vector_db.find_by_payload(&filter);
                     return_sender.send(Response::Indexes(indexes)).unwrap();
                 }
-                FetchIndexData(indices, return_address) => {
-                    let data = vector_db.get_indexes(indices);
-                    return_address.send(Response::Data(data)).unwrap()
+                Explain(filter, return_sender) => {
+                    let plan = vector_db.explain(filter.as_ref(), NUM_INDEXES);
+                    return_sender.send(Response::Plan(plan)).unwrap();
+                }
+                Search(request, return_sender) => {
+                    let k = request.k.unwrap_or(NUM_INDEXES);
+                    let response = match check_search(k, request.mmr.as_ref()).and_then(|()| vector_db.search(&request, k)) {
+                        Ok(hits) => Response::Hits(hits),
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                BatchSearch(request, return_sender) => {
+                    let k = request.k.unwrap_or(NUM_INDEXES);
+                    let results = vector_db
+                        .search_batch(request.queries, k, request.filter.as_ref(), request.with_vector)
+                        .into_iter()
+                        .map(|result| match result {
+                            Ok(hits) => Response::Hits(hits),
+                            Err(e) => Response::Error(e),
+                        })
+                        .collect();
+                    return_sender.send(Response::BatchHits(results)).unwrap();
+                }
+                Recommend(request, return_sender) => {
+                    let k = request.k.unwrap_or(NUM_INDEXES);
+                    let response = match vector_db.recommend(
+                        &request.positive,
+                        &request.negative,
+                        k,
+                        request.filter.as_ref(),
+                        request.with_vector,
+                    ) {
+                        Ok(hits) => Response::Hits(hits),
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                GroupSearch(request, return_sender) => {
+                    let response = match vector_db.search_groups(&request) {
+                        Ok(groups) => Response::Groups(groups),
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                RangeSearch(request, return_sender) => {
+                    range_search(&vector_db, request, return_sender);
+                }
+                InsertVector(request, return_sender) => {
+                    let result = vector_db.insert_vector(request.entry.clone(), request);
+                    return_sender.send(inserted(result)).unwrap();
+                }
+                UpdateSparse(request, return_sender) => {
+                    let response = match vector_db.set_sparse(request.id, &request.sparse) {
+                        Ok(()) => Response::Success,
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                SearchSparse(request, return_sender) => {
+                    let k = request.k.unwrap_or(NUM_INDEXES);
+                    let response = match vector_db.search_sparse(&request, k) {
+                        Ok(hits) => Response::Hits(hits),
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                InsertMultiVector(request, return_sender) => {
+                    let result = vector_db.insert_multi_vector(
+                        request.entry.clone(),
+                        request.entry,
+                        request.vectors,
+                        request.payload,
+                    );
+                    return_sender.send(inserted(result)).unwrap();
+                }
+                SearchMultiVector(request, return_sender) => {
+                    let k = request.k.unwrap_or(NUM_INDEXES);
+                    let response = match vector_db.search_multi_vector(&request, k) {
+                        Ok(hits) => Response::Hits(hits),
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
                 }
-                Kill => {
+                CreateVectorSpace(request, return_sender) => {
+                    let response = match create_vector_space(&mut vector_db, request) {
+                        Ok(()) => Response::Success,
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                SetNamedVector(request, return_sender) => {
+                    let response = match vector_db.set_named_vector(&request) {
+                        Ok(()) => Response::Success,
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                SetTextPipeline(pipeline, return_sender) => {
+                    let response = match vector_db.set_text_pipeline(pipeline) {
+                        Ok(()) => Response::Success,
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                SetInstructions(instructions, return_sender) => {
+                    let response = match vector_db.set_instructions(instructions) {
+                        Ok(()) => Response::Success,
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                SetDedupePolicy(policy, return_sender) => {
+                    vector_db.set_dedupe_policy(policy);
+                    return_sender.send(Response::Success).unwrap();
+                }
+                ConfigureEmbeddingCache(request, return_sender) => {
+                    let response = match vector_db.configure_embedding_cache(request.capacity, request.disk_dir) {
+                        Ok(()) => Response::Success,
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                EmbeddingCacheStats(return_sender) => {
+                    return_sender.send(Response::CacheStats(vector_db.embedding_cache_stats())).unwrap();
+                }
+                CollectionInfo(return_sender) => {
+                    return_sender.send(Response::Collection(vector_db.info())).unwrap();
+                }
+                Reembed(request, return_sender) => {
+                    let started = match (&request.model_path, &request.embedder) {
+                        (None, None) => Err("Re-embed needs a model_path or an embedder".to_string()),
+                        _ => vector_db.start_reembed(),
+                    };
+                    let response = match started {
+                        Ok(job) => {
+                            let job_address = job_address.clone();
+                            tokio::task::spawn_blocking(move || {
+                                let output = build_embedder(request.model_path.as_deref(), request.embedder.as_ref())
+                                    .and_then(|embedder| reembed::run(job, embedder.unwrap(), request.dims));
+                                if let Some(db_address) = job_address.upgrade() {
+                                    let _ = db_address.blocking_send(FinishReembed(output));
+                                }
+                            });
+                            Response::Success
+                        }
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                FinishReembed(output) => match vector_db.finish_reembed(output) {
+                    Ok(count) => println!("Re-embed: swapped in {} records", count),
+                    Err(e) => println!("Re-embed failed, keeping the old vectors: {}", e),
+                },
+                SearchVector(request, return_sender) => {
+                    let k = request.k.unwrap_or(NUM_INDEXES);
+                    let hits = check_search(k, request.mmr.as_ref()).and_then(|()| vector_db.search_vector(&request, k));
+                    let response = match hits {
+                        Ok(hits) => Response::Hits(hits),
+                        Err(e) => Response::Error(e),
+                    };
+                    return_sender.send(response).unwrap();
+                }
+                Kill | Null => {
                     break
                 }
             }
         }
     });
 
-    (db_process, tx)
+    Ok((db_process, tx))
 }
 
-async fn handle_insert(request: &str, db_address: Sender<DbCalls>) -> Response {
-    let (tx, mut rx) = tokio::sync::oneshot::channel();
-    if let Some(body) = request.split("\r\n\r\n").nth(1) {
-        if let Ok(insert_req) = serde_json::from_str::<InsertRequest>(body) {
-            db_address.send(Insert(insert_req.entry, tx)).await.unwrap();
-            rx.await.unwrap()
-        } else {
-            Response::Error("Invalid JSON for insert".to_string())
+/// Streams chunks in page order when asked to; otherwise collects every hit
+/// so the best `limit` can be sent back sorted.
+fn range_search(vector_db: &VectorDB<String>, request: RangeSearchRequest, return_sender: mpsc::UnboundedSender<Response>) {
+    let result = if request.stream {
+        let mut chunk = Vec::with_capacity(RANGE_CHUNK_SIZE);
+        let result = vector_db.range_search(&request, |hit| {
+            chunk.push(hit);
+            if chunk.len() == RANGE_CHUNK_SIZE {
+                let _ = return_sender.send(Response::Hits(std::mem::take(&mut chunk)));
+            }
+        });
+        if !chunk.is_empty() {
+            let _ = return_sender.send(Response::Hits(chunk));
         }
+        result
     } else {
-        Response::Error("No body in insert request".to_string())
+        let limit = request.limit;
+        let unlimited = RangeSearchRequest { limit: None, ..request };
+        let mut hits = vec![];
+        let result = vector_db.range_search(&unlimited, |hit| hits.push(hit));
+        hits.sort_by(|a, b| a.metric.compare(a.score, b.score));
+        hits.truncate(limit.unwrap_or(usize::MAX));
+        let _ = return_sender.send(Response::Hits(hits));
+        result
+    };
+    if let Err(e) = result {
+        let _ = return_sender.send(Response::Error(e));
     }
 }
 
-async fn handle_get(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
-    if let Some(id) = request.split("?id=").nth(1) {
-        let id = id.split_whitespace().next().unwrap_or("");
-        let (sender, receiver) = tokio::sync::oneshot::channel();
-        db_address.send(FindIndexes(id.to_string(), sender)).await.unwrap();
-        match receiver.await.unwrap() {
-            Response::Indexes(indexes) => {
-                db_address.send(DbCalls::FetchIndexData(indexes, sender)).await.unwrap();
-                receiver.await.unwrap()
+fn inserted(result: Result<Stored, String>) -> Response {
+    match result {
+        Ok(stored) => Response::Inserted { id: stored.id(), duplicate: stored.is_duplicate() },
+        Err(e) => Response::Error(e),
+    }
+}
+
+/// `model_path` is shorthand for a llamafile model, `spec` picks any other kind.
+fn build_embedder(model_path: Option<&str>, spec: Option<&EmbedderSpec>) -> Result<Option<Box<dyn Embedder + Send>>, String> {
+    match (model_path, spec) {
+        (Some(_), Some(_)) => Err("Give either model_path or embedder, not both".to_string()),
+        (Some(path), None) => Ok(Some(Box::new(LlamafileEmbedding::new(path)?))),
+        (None, Some(spec)) => spec.build().map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+fn create_vector_space(vector_db: &mut VectorDB<String>, request: CreateVectorSpaceRequest) -> Result<(), String> {
+    let embedder = build_embedder(request.model_path.as_deref(), request.embedder.as_ref())?;
+    let config = CollectionConfig {
+        dims: request.dims,
+        metric: request.metric,
+        model_id: None,
+    };
+    vector_db.create_vector_space(request.name, VectorSpace::new(config, embedder)?)
+}
+
+/// Rejects search options that can't produce a sensible ranking.
+fn check_search(k: usize, mmr: Option<&MmrOptions>) -> Result<(), String> {
+    if k == 0 {
+        return Err("k must be at least 1".to_string());
+    }
+    mmr.map_or(Ok(()), |mmr| mmr.validate())
+}
+
+/// Text lines go through the batched embedder together; lines that brought
+/// their own vector are inserted directly.
+fn ingest_batch(vector_db: &mut VectorDB<String>, lines: Vec<IngestLine>) -> Vec<InsertResult> {
+    let mut results = Vec::with_capacity(lines.len());
+    let mut text_positions = vec![];
+    let mut text_items = vec![];
+    for (index, line) in lines.into_iter().enumerate() {
+        match line.vector {
+            Some(vector) => {
+                let request = InsertVectorRequest {
+                    entry: line.entry,
+                    vector,
+                    payload: line.payload,
+                    sparse: line.sparse,
+                    named: line.named,
+                    model: line.model,
+                };
+                let result = vector_db.insert_vector(request.entry.clone(), request);
+                results.push(InsertResult::from_result(index, result));
+            }
+            None if line.chunk.is_some() => {
+                let result = match line.sparse {
+                    Some(_) => Err("Sparse vectors can't be combined with chunking".to_string()),
+                    None => vector_db
+                        .insert_chunked(&line.entry, line.payload, line.chunk.as_ref().unwrap())
+                        .map(Stored::New),
+                };
+                results.push(InsertResult::from_result(index, result));
+            }
+            None => {
+                if let Some(Err(e)) = line.sparse.as_ref().map(|x| x.validate()) {
+                    results.push(InsertResult::from_result(index, Err(e)));
+                    continue;
+                }
+                text_positions.push((index, line.sparse));
+                text_items.push((line.entry.clone(), line.entry, line.payload));
             }
-            _ => Response::Error("Invalid Response from DB".to_string()),
         }
+    }
+    for ((index, sparse), result) in text_positions.into_iter().zip(vector_db.insert_batch(text_items)) {
+        // A skipped or merged duplicate keeps the existing record's sparse vector, same as insert_vector.
+        let result = match (result, sparse) {
+            (Ok(stored), Some(sparse)) if stored.created().is_some() => {
+                vector_db.set_sparse(stored.id(), &sparse).map(|_| stored)
+            }
+            (result, _) => result,
+        };
+        results.push(InsertResult::from_result(index, result));
+    }
+    results.sort_by_key(|x| x.index);
+    results
+}
+
+/// Sends a call that needs nothing but a reply channel and waits for the answer.
+async fn ask_db(db_address: &mpsc::Sender<DbCalls>, call: impl FnOnce(oneshot::Sender<Response>) -> DbCalls) -> Response {
+    let (sender, receiver) = oneshot::channel();
+    db_address.send(call(sender)).await.unwrap();
+    receiver.await.unwrap()
+}
+
+/// Parses the JSON body of an HTTP request, `what` names it in the error.
+fn parse_body<R: DeserializeOwned>(request: &str, what: &str) -> Result<R, String> {
+    let body = request
+        .split("\r\n\r\n")
+        .nth(1)
+        .ok_or_else(|| format!("No body in {} request", what))?;
+    serde_json::from_str(body).map_err(|_| format!("Invalid JSON for {}", what))
+}
+
+/// The body of every plain request handler: parse, build the call, wait.
+async fn call_db<R: DeserializeOwned>(
+    request: &str,
+    db_address: &mpsc::Sender<DbCalls>,
+    what: &str,
+    call: impl FnOnce(R, oneshot::Sender<Response>) -> DbCalls,
+) -> Response {
+    match parse_body(request, what) {
+        Ok(parsed) => ask_db(db_address, |sender| call(parsed, sender)).await,
+        Err(e) => Response::Error(e),
+    }
+}
+
+pub(crate) async fn handle_insert(request: &str, db_address: Sender<DbCalls>) -> Response {
+    call_db(request, &db_address, "insert", Insert).await
+}
+
+pub(crate) async fn handle_batch_insert(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "batch insert", |req: BatchInsertRequest, sender| BatchInsert(req.entries, sender)).await
+}
 
+pub(crate) async fn handle_get(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    if let Some(id) = request.split("?id=").nth(1) {
+        let id = id.split_whitespace().next().unwrap_or("");
+        let search_req = SearchRequest {
+            query: id.to_string(),
+            k: None,
+            filter: None,
+            with_vector: false,
+            mmr: None,
+            hybrid: None,
+            using: None,
+            collapse_chunks: false,
+        };
+        ask_db(db_address, |sender| Search(search_req, sender)).await
     } else {
         Response::Error("No id provided in get request".to_string())
     }
-}
\ No newline at end of file
+}
+
+pub(crate) async fn handle_search(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "search", Search).await
+}
+
+pub(crate) async fn handle_batch_search(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "batch search", BatchSearch).await
+}
+
+pub(crate) async fn handle_recommend(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "recommend", Recommend).await
+}
+
+/// Streaming range searches write each chunk to the socket as an NDJSON
+/// line as it arrives; the caller writes the final response line after.
+pub(crate) async fn handle_range_search(request: &str, db_address: &mpsc::Sender<DbCalls>, socket: &mut TcpStream) -> Response {
+    let range_req = match parse_body::<RangeSearchRequest>(request, "range search") {
+        Ok(range_req) => range_req,
+        Err(e) => return Response::Error(e),
+    };
+    let stream = range_req.stream;
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    db_address.send(RangeSearch(range_req, sender)).await.unwrap();
+    let mut hits = vec![];
+    while let Some(response) = receiver.recv().await {
+        match response {
+            Response::Hits(chunk) if stream => {
+                let mut line = serde_json::to_string(&Response::Hits(chunk)).unwrap();
+                line.push('\n');
+                socket.write_all(line.as_bytes()).await.unwrap();
+            }
+            Response::Hits(chunk) => hits.extend(chunk),
+            other => return other,
+        }
+    }
+    if stream {
+        Response::Success
+    } else {
+        Response::Hits(hits)
+    }
+}
+
+pub(crate) async fn handle_group_search(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "group search", GroupSearch).await
+}
+
+pub(crate) async fn handle_insert_vector(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "vector insert", InsertVector).await
+}
+
+pub(crate) async fn handle_search_vector(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "vector search", SearchVector).await
+}
+
+pub(crate) async fn handle_update_payload(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "payload update", |req: UpdatePayloadRequest, sender| {
+        UpdatePayload(req.id, req.payload, sender)
+    })
+    .await
+}
+
+pub(crate) async fn handle_update_sparse(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "sparse update", UpdateSparse).await
+}
+
+pub(crate) async fn handle_search_sparse(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "sparse search", SearchSparse).await
+}
+
+pub(crate) async fn handle_insert_multi_vector(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "multi-vector insert", InsertMultiVector).await
+}
+
+pub(crate) async fn handle_search_multi_vector(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "multi-vector search", SearchMultiVector).await
+}
+
+pub(crate) async fn handle_create_vector_space(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "vector space creation", CreateVectorSpace).await
+}
+
+pub(crate) async fn handle_set_named_vector(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "named vector update", SetNamedVector).await
+}
+
+pub(crate) async fn handle_set_text_pipeline(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "text pipeline", SetTextPipeline).await
+}
+
+pub(crate) async fn handle_set_instructions(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "instruction templates", SetInstructions).await
+}
+
+pub(crate) async fn handle_collection_info(db_address: &mpsc::Sender<DbCalls>) -> Response {
+    ask_db(db_address, CollectionInfo).await
+}
+
+pub(crate) async fn handle_reembed(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "re-embed", Reembed).await
+}
+
+pub(crate) async fn handle_set_dedupe_policy(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "dedupe policy", SetDedupePolicy).await
+}
+
+pub(crate) async fn handle_configure_embedding_cache(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "embedding cache config", ConfigureEmbeddingCache).await
+}
+
+pub(crate) async fn handle_embedding_cache_stats(db_address: &mpsc::Sender<DbCalls>) -> Response {
+    ask_db(db_address, EmbeddingCacheStats).await
+}
+
+pub(crate) async fn handle_delete(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "delete", |req: DeleteRequest, sender| Delete(req.id, sender)).await
+}
+
+pub(crate) async fn handle_create_index(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "create index", |req: CreateIndexRequest, sender| {
+        CreatePayloadIndex(req.key, req.index_type, sender)
+    })
+    .await
+}
+
+pub(crate) async fn handle_find(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "find", |req: FilterRequest, sender| {
+        FindByPayload(req.filter.unwrap_or(Filter::And(vec![])), sender)
+    })
+    .await
+}
+
+pub(crate) async fn handle_explain(request: &str, db_address: &mpsc::Sender<DbCalls>) -> Response {
+    call_db(request, db_address, "explain", |req: FilterRequest, sender| Explain(req.filter, sender)).await
+}
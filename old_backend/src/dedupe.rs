@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Payload key set on records inserted under `DuplicateAction::InsertAndFlag`.
+pub(crate) const DUPLICATE_OF_KEY: &str = "duplicate_of";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) enum DuplicateAction {
+    /// Drop the new record; the insert reports the existing id.
+    #[default]
+    Skip,
+    /// Fold the new payload into the existing record, new values winning.
+    MergePayload,
+    /// Store it anyway with `duplicate_of` pointing at the existing record.
+    InsertAndFlag,
+}
+
+/// What counts as a duplicate at insert time and what to do about one.
+/// The default policy checks nothing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct DedupePolicy {
+    /// Same text after the collection's text pipeline.
+    #[serde(default)]
+    pub exact: bool,
+    /// Nearest stored vector scoring at least this close.
+    pub near_threshold: Option<f32>,
+    #[serde(default)]
+    pub action: DuplicateAction,
+}
+
+pub(crate) fn content_hash(text: &str) -> [u8; 32] {
+    Sha256::digest(text.as_bytes()).into()
+}
@@ -0,0 +1,59 @@
+use std::fs;
+use std::time::UNIX_EPOCH;
+use serde::{Deserialize, Serialize};
+use crate::encoder_embedding::{EncoderConfig, EncoderEmbedding};
+use crate::static_embedding::{StaticEmbedding, StaticEmbeddingConfig};
+
+/// Anything that can turn text into a fixed-length vector for `VectorDB`.
+pub(crate) trait Embedder {
+    /// Length of every vector `get_embedding` returns.
+    fn dims(&self) -> usize;
+
+    /// Stable name for the model and its settings; two embedders with the
+    /// same id must produce the same vectors. Keys the embedding cache.
+    fn model_id(&self) -> String;
+
+    /// Fails rather than making up a vector when the text can't be embedded,
+    /// so nothing bogus gets stored or cached.
+    fn get_embedding(&self, text: &str) -> Result<Vec<f32>, String>;
+
+    /// One result per text, in order. Embedders with a real batch path
+    /// should override this; the default just loops.
+    fn get_embeddings(&self, texts: &[String]) -> Vec<Result<Vec<f32>, String>> {
+        texts.iter().map(|text| self.get_embedding(text)).collect()
+    }
+}
+
+/// `path` tagged with the file's size and modification time, for model ids:
+/// a different model copied over the same path gets a different id, so it
+/// can't pass for the old one or read the old one's cached vectors.
+pub(crate) fn file_id(path: &str) -> String {
+    let Ok(meta) = fs::metadata(path) else {
+        return path.to_string();
+    };
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |x| x.as_nanos());
+    format!("{}@{}.{}", path, meta.len(), modified)
+}
+
+/// In-process embedders a request can ask for by `kind`; llamafile models are
+/// still picked with a plain `model_path`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum EmbedderSpec {
+    Static(StaticEmbeddingConfig),
+    Encoder(EncoderConfig),
+}
+
+impl EmbedderSpec {
+    /// Loads the model; slow for big weight files.
+    pub fn build(&self) -> Result<Box<dyn Embedder + Send>, String> {
+        match self {
+            EmbedderSpec::Static(config) => Ok(Box::new(StaticEmbedding::load(config)?)),
+            EmbedderSpec::Encoder(config) => Ok(Box::new(EncoderEmbedding::load(config)?)),
+        }
+    }
+}
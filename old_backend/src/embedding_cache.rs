@@ -0,0 +1,110 @@
+use std::cell::RefCell;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct CacheStats {
+    pub memory_hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CacheConfigRequest {
+    pub capacity: usize,
+    /// Directory to persist entries in, one file per key. Memory only when None.
+    pub disk_dir: Option<String>,
+}
+
+/// Content-addressed embedding store keyed by model id plus the text as the
+/// embedder saw it, so any embedder can share it. Uses interior mutability
+/// because lookups happen on the read-only query paths too.
+pub(crate) struct EmbeddingCache {
+    memory: RefCell<LruCache<[u8; 32], Vec<f32>>>,
+    disk_dir: Option<PathBuf>,
+    stats: RefCell<CacheStats>,
+}
+
+impl EmbeddingCache {
+    pub fn new(capacity: usize, disk_dir: Option<PathBuf>) -> Result<Self, String> {
+        let capacity = NonZeroUsize::new(capacity).ok_or("Cache capacity must be at least 1".to_string())?;
+        if let Some(dir) = &disk_dir {
+            fs::create_dir_all(dir).map_err(|e| format!("Can't create cache dir {}: {}", dir.display(), e))?;
+        }
+        Ok(Self {
+            memory: RefCell::new(LruCache::new(capacity)),
+            disk_dir,
+            stats: RefCell::new(CacheStats::default()),
+        })
+    }
+
+    pub fn key(model_id: &str, text: &str) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(model_id.as_bytes());
+        // Separator so ("ab", "c") and ("a", "bc") can't collide.
+        hasher.update([0]);
+        hasher.update(text.as_bytes());
+        hasher.finalize().into()
+    }
+
+    pub fn get(&self, key: &[u8; 32]) -> Option<Vec<f32>> {
+        if let Some(vector) = self.memory.borrow_mut().get(key) {
+            self.stats.borrow_mut().memory_hits += 1;
+            return Some(vector.clone());
+        }
+        if let Some(vector) = self.read_disk(key) {
+            self.stats.borrow_mut().disk_hits += 1;
+            self.memory.borrow_mut().put(*key, vector.clone());
+            return Some(vector);
+        }
+        self.stats.borrow_mut().misses += 1;
+        None
+    }
+
+    pub fn put(&self, key: [u8; 32], vector: &[f32]) {
+        if let Some(path) = self.disk_path(&key) {
+            let bytes: Vec<u8> = vector.iter().flat_map(|x| x.to_le_bytes()).collect();
+            // A failed write only costs a future recompute.
+            let _ = fs::write(path, bytes);
+        }
+        self.memory.borrow_mut().put(key, vector.to_vec());
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = self.stats.borrow().clone();
+        let lookups = stats.memory_hits + stats.disk_hits + stats.misses;
+        stats.hit_rate = if lookups == 0 {
+            0.0
+        } else {
+            (stats.memory_hits + stats.disk_hits) as f64 / lookups as f64
+        };
+        stats.entries = self.memory.borrow().len();
+        stats.capacity = self.memory.borrow().cap().get();
+        stats
+    }
+
+    fn disk_path(&self, key: &[u8; 32]) -> Option<PathBuf> {
+        let name: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        self.disk_dir.as_ref().map(|dir| dir.join(name))
+    }
+
+    fn read_disk(&self, key: &[u8; 32]) -> Option<Vec<f32>> {
+        let bytes = fs::read(self.disk_path(key)?).ok()?;
+        if bytes.len() % 4 != 0 {
+            return None;
+        }
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+                .collect(),
+        )
+    }
+}
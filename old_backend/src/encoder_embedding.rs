@@ -0,0 +1,589 @@
+use std::fs;
+use std::thread;
+use serde::{Deserialize, Serialize};
+use tokenizers::{Tokenizer, TruncationParams};
+use crate::embedder::{file_id, Embedder};
+use crate::weights::Weights;
+
+const DEFAULT_BATCH_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Pooling {
+    /// Final hidden state of the leading [CLS] token.
+    Cls,
+    #[default]
+    Mean,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct EncoderConfig {
+    /// `model.safetensors` of a HuggingFace BERT-style model.
+    pub weights_path: String,
+    /// Its `config.json`.
+    pub config_path: String,
+    pub tokenizer_path: String,
+    #[serde(default)]
+    pub pooling: Pooling,
+    /// Longer inputs are truncated; capped at the model's position count.
+    pub max_seq_len: Option<usize>,
+    /// Texts run through the model concurrently, one thread each.
+    pub batch_size: Option<usize>,
+}
+
+/// The parts of a HuggingFace BERT `config.json` the forward pass needs.
+#[derive(Debug, Deserialize)]
+struct BertConfig {
+    hidden_size: usize,
+    num_hidden_layers: usize,
+    num_attention_heads: usize,
+    intermediate_size: usize,
+    max_position_embeddings: usize,
+    #[serde(default = "default_type_vocab_size")]
+    type_vocab_size: usize,
+    #[serde(default = "default_layer_norm_eps")]
+    layer_norm_eps: f32,
+    #[serde(default = "default_hidden_act")]
+    hidden_act: String,
+}
+
+fn default_type_vocab_size() -> usize {
+    2
+}
+
+fn default_layer_norm_eps() -> f32 {
+    1e-12
+}
+
+fn default_hidden_act() -> String {
+    "gelu".to_string()
+}
+
+struct Linear {
+    /// Row-major [out, in], as PyTorch stores it.
+    weight: Vec<f32>,
+    bias: Vec<f32>,
+    inputs: usize,
+}
+
+impl Linear {
+    fn load(weights: &Weights, name: &str, outputs: usize, inputs: usize) -> Result<Self, String> {
+        Ok(Self {
+            weight: weights.tensor_shaped(&format!("{}.weight", name), &[outputs, inputs])?,
+            bias: weights.tensor_shaped(&format!("{}.bias", name), &[outputs])?,
+            inputs,
+        })
+    }
+
+    /// `x` is [tokens, in]; returns [tokens, out].
+    fn forward(&self, x: &[f32]) -> Vec<f32> {
+        let mut out = Vec::with_capacity(x.len() / self.inputs * self.bias.len());
+        for row in x.chunks(self.inputs) {
+            for (weights, bias) in self.weight.chunks(self.inputs).zip(&self.bias) {
+                out.push(bias + dot(weights, row));
+            }
+        }
+        out
+    }
+}
+
+struct LayerNorm {
+    weight: Vec<f32>,
+    bias: Vec<f32>,
+    eps: f32,
+}
+
+impl LayerNorm {
+    fn load(weights: &Weights, name: &str, size: usize, eps: f32) -> Result<Self, String> {
+        Ok(Self {
+            weight: weights.tensor_shaped(&format!("{}.weight", name), &[size])?,
+            bias: weights.tensor_shaped(&format!("{}.bias", name), &[size])?,
+            eps,
+        })
+    }
+
+    fn forward(&self, x: &mut [f32]) {
+        for row in x.chunks_mut(self.weight.len()) {
+            let mean = row.iter().sum::<f32>() / row.len() as f32;
+            let var = row.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / row.len() as f32;
+            let scale = 1.0 / (var + self.eps).sqrt();
+            for ((v, w), b) in row.iter_mut().zip(&self.weight).zip(&self.bias) {
+                *v = (*v - mean) * scale * w + b;
+            }
+        }
+    }
+}
+
+struct Layer {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    attention_out: Linear,
+    attention_norm: LayerNorm,
+    intermediate: Linear,
+    output: Linear,
+    output_norm: LayerNorm,
+}
+
+impl Layer {
+    fn load(weights: &Weights, prefix: &str, config: &BertConfig) -> Result<Self, String> {
+        let (hidden, inner, eps) = (config.hidden_size, config.intermediate_size, config.layer_norm_eps);
+        Ok(Self {
+            query: Linear::load(weights, &format!("{}.attention.self.query", prefix), hidden, hidden)?,
+            key: Linear::load(weights, &format!("{}.attention.self.key", prefix), hidden, hidden)?,
+            value: Linear::load(weights, &format!("{}.attention.self.value", prefix), hidden, hidden)?,
+            attention_out: Linear::load(weights, &format!("{}.attention.output.dense", prefix), hidden, hidden)?,
+            attention_norm: LayerNorm::load(weights, &format!("{}.attention.output.LayerNorm", prefix), hidden, eps)?,
+            intermediate: Linear::load(weights, &format!("{}.intermediate.dense", prefix), inner, hidden)?,
+            output: Linear::load(weights, &format!("{}.output.dense", prefix), hidden, inner)?,
+            output_norm: LayerNorm::load(weights, &format!("{}.output.LayerNorm", prefix), hidden, eps)?,
+        })
+    }
+
+    /// Post-norm transformer block over one unpadded sequence, in place.
+    fn forward(&self, x: &mut [f32], heads: usize) {
+        let hidden = self.attention_norm.weight.len();
+        let tokens = x.len() / hidden;
+        let head_dims = hidden / heads;
+        let scale = 1.0 / (head_dims as f32).sqrt();
+        let (q, k, v) = (self.query.forward(x), self.key.forward(x), self.value.forward(x));
+        let mut context = vec![0.0; x.len()];
+        let mut scores = vec![0.0; tokens];
+        for head in 0..heads {
+            let span = head * head_dims..(head + 1) * head_dims;
+            for i in 0..tokens {
+                let qi = &q[i * hidden..][span.clone()];
+                for (j, score) in scores.iter_mut().enumerate() {
+                    *score = dot(qi, &k[j * hidden..][span.clone()]) * scale;
+                }
+                softmax(&mut scores);
+                let out = &mut context[i * hidden..][span.clone()];
+                for (j, p) in scores.iter().enumerate() {
+                    for (o, vj) in out.iter_mut().zip(&v[j * hidden..][span.clone()]) {
+                        *o += p * vj;
+                    }
+                }
+            }
+        }
+        let attended = self.attention_out.forward(&context);
+        x.iter_mut().zip(attended).for_each(|(a, b)| *a += b);
+        self.attention_norm.forward(x);
+
+        let mut inner = self.intermediate.forward(x);
+        inner.iter_mut().for_each(|v| *v = gelu(*v));
+        let out = self.output.forward(&inner);
+        x.iter_mut().zip(out).for_each(|(a, b)| *a += b);
+        self.output_norm.forward(x);
+    }
+}
+
+/// A BERT-style sentence encoder run on the CPU in plain Rust. Meant for
+/// small models (MiniLM, bge-small and the like); there's no SIMD or BLAS.
+pub struct EncoderEmbedding {
+    tokenizer: Tokenizer,
+    word_embeddings: Vec<f32>,
+    position_embeddings: Vec<f32>,
+    token_type_embeddings: Vec<f32>,
+    embedding_norm: LayerNorm,
+    layers: Vec<Layer>,
+    hidden: usize,
+    heads: usize,
+    pooling: Pooling,
+    batch_size: usize,
+    model_id: String,
+}
+
+impl EncoderEmbedding {
+    pub fn load(config: &EncoderConfig) -> Result<Self, String> {
+        let text = fs::read_to_string(&config.config_path)
+            .map_err(|e| format!("Can't read model config {}: {}", config.config_path, e))?;
+        let bert: BertConfig =
+            serde_json::from_str(&text).map_err(|e| format!("Invalid model config {}: {}", config.config_path, e))?;
+        if bert.hidden_act != "gelu" {
+            return Err(format!("Unsupported activation {}, only gelu is implemented", bert.hidden_act));
+        }
+        if !bert.hidden_size.is_multiple_of(bert.num_attention_heads) {
+            return Err("hidden_size must be a multiple of num_attention_heads".to_string());
+        }
+        let hidden = bert.hidden_size;
+
+        let weights = Weights::load(&config.weights_path)?;
+        // Task-head checkpoints (BertForMaskedLM etc.) nest the encoder under `bert.`.
+        let prefix = if weights.tensor("embeddings.word_embeddings.weight").is_ok() { "" } else { "bert." };
+        let (word_embeddings, shape) = weights.tensor(&format!("{}embeddings.word_embeddings.weight", prefix))?;
+        if shape.len() != 2 || shape[1] != hidden {
+            return Err(format!("Word embeddings have shape {:?}, expected [vocab, {}]", shape, hidden));
+        }
+        let position_embeddings = weights.tensor_shaped(
+            &format!("{}embeddings.position_embeddings.weight", prefix),
+            &[bert.max_position_embeddings, hidden],
+        )?;
+        let token_type_embeddings = weights.tensor_shaped(
+            &format!("{}embeddings.token_type_embeddings.weight", prefix),
+            &[bert.type_vocab_size, hidden],
+        )?;
+        let embedding_norm =
+            LayerNorm::load(&weights, &format!("{}embeddings.LayerNorm", prefix), hidden, bert.layer_norm_eps)?;
+        let layers = (0..bert.num_hidden_layers)
+            .map(|i| Layer::load(&weights, &format!("{}encoder.layer.{}", prefix, i), &bert))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let max_seq_len = config
+            .max_seq_len
+            .unwrap_or(bert.max_position_embeddings)
+            .min(bert.max_position_embeddings);
+        let mut tokenizer = Tokenizer::from_file(&config.tokenizer_path)
+            .map_err(|e| format!("Can't load tokenizer {}: {}", config.tokenizer_path, e))?;
+        // Every sequence runs on its own, so padding would only be wasted work.
+        tokenizer.with_padding(None);
+        tokenizer
+            .with_truncation(Some(TruncationParams { max_length: max_seq_len, ..Default::default() }))
+            .map_err(|e| format!("Can't truncate to {} tokens: {}", max_seq_len, e))?;
+
+        let model_id = format!(
+            "encoder:{}:{}:{:?}:{}",
+            file_id(&config.weights_path),
+            file_id(&config.tokenizer_path),
+            config.pooling,
+            max_seq_len
+        );
+        Ok(Self {
+            tokenizer,
+            word_embeddings,
+            position_embeddings,
+            token_type_embeddings,
+            embedding_norm,
+            layers,
+            hidden,
+            heads: bert.num_attention_heads,
+            pooling: config.pooling,
+            batch_size: config.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1),
+            model_id,
+        })
+    }
+
+    fn encode(&self, ids: &[u32], type_ids: &[u32]) -> Vec<f32> {
+        let hidden = self.hidden;
+        let vocab = self.word_embeddings.len() / hidden;
+        let types = self.token_type_embeddings.len() / hidden;
+        let mut x = Vec::with_capacity(ids.len() * hidden);
+        for (position, (&id, &type_id)) in ids.iter().zip(type_ids).enumerate() {
+            // Out of range ids only come from a mismatched tokenizer; clamp rather than panic.
+            let word = &self.word_embeddings[(id as usize).min(vocab - 1) * hidden..][..hidden];
+            let place = &self.position_embeddings[position * hidden..][..hidden];
+            let kind = &self.token_type_embeddings[(type_id as usize).min(types - 1) * hidden..][..hidden];
+            x.extend(word.iter().zip(place).zip(kind).map(|((a, b), c)| a + b + c));
+        }
+        self.embedding_norm.forward(&mut x);
+        for layer in &self.layers {
+            layer.forward(&mut x, self.heads);
+        }
+        let mut pooled = match self.pooling {
+            Pooling::Cls => x[..hidden].to_vec(),
+            Pooling::Mean => {
+                let mut sum = vec![0.0; hidden];
+                for row in x.chunks(hidden) {
+                    sum.iter_mut().zip(row).for_each(|(a, b)| *a += b);
+                }
+                sum.iter_mut().for_each(|v| *v /= ids.len() as f32);
+                sum
+            }
+        };
+        let norm = dot(&pooled, &pooled).sqrt();
+        if norm > 0.0 {
+            pooled.iter_mut().for_each(|v| *v /= norm);
+        }
+        pooled
+    }
+}
+
+impl Embedder for EncoderEmbedding {
+    fn dims(&self) -> usize {
+        self.hidden
+    }
+
+    fn model_id(&self) -> String {
+        self.model_id.clone()
+    }
+
+    fn get_embedding(&self, text: &str) -> Result<Vec<f32>, String> {
+        self.get_embeddings(&[text.to_string()]).pop().unwrap()
+    }
+
+    fn get_embeddings(&self, texts: &[String]) -> Vec<Result<Vec<f32>, String>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size) {
+            let encodings: Vec<_> = batch
+                .iter()
+                .map(|text| match self.tokenizer.encode(text.as_str(), true) {
+                    Ok(encoding) if encoding.get_ids().is_empty() => Err("Text has no tokens".to_string()),
+                    Ok(encoding) => Ok(encoding),
+                    Err(e) => Err(format!("Can't tokenize text: {}", e)),
+                })
+                .collect();
+            thread::scope(|scope| {
+                let handles: Vec<_> = encodings
+                    .into_iter()
+                    .map(|encoding| {
+                        scope.spawn(move || {
+                            encoding.map(|encoding| self.encode(encoding.get_ids(), encoding.get_type_ids()))
+                        })
+                    })
+                    .collect();
+                vectors.extend(handles.into_iter().map(|handle| handle.join().unwrap()));
+            });
+        }
+        vectors
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn softmax(x: &mut [f32]) {
+    let max = x.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let mut total = 0.0;
+    for v in x.iter_mut() {
+        *v = (*v - max).exp();
+        total += *v;
+    }
+    x.iter_mut().for_each(|v| *v /= total);
+}
+
+/// Exact (erf) GELU, which is what BERT's "gelu" means.
+fn gelu(x: f32) -> f32 {
+    0.5 * x * (1.0 + erf(x / std::f32::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun 7.1.26; absolute error below 1.5e-7.
+fn erf(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t * (0.254_829_6 + t * (-0.284_496_7 + t * (1.421_413_8 + t * (-1.453_152 + t * 1.061_405_4))));
+    let y = 1.0 - poly * (-x * x).exp();
+    if x < 0.0 {
+        -y
+    } else {
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{RngCore, SeedableRng};
+    use safetensors::tensor::TensorView;
+    use safetensors::Dtype;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    const HIDDEN: usize = 8;
+    const INTERMEDIATE: usize = 16;
+    const POSITIONS: usize = 32;
+    const WORDS: usize = 10;
+    // [UNK], [CLS], [SEP], then w0..w9.
+    const VOCAB: usize = WORDS + 3;
+
+    /// Writes a random two-layer BERT, its config and a word-level tokenizer
+    /// to a fresh directory under the system temp dir.
+    fn write_model(name: &str) -> PathBuf {
+        let mut rng = StdRng::seed_from_u64(7);
+        write_model_with(name, None, |_, _, _| rng.next_u32() as f32 / u32::MAX as f32 - 0.5)
+    }
+
+    /// Like `write_model`, with `fill(name, tensor, element)` giving every
+    /// weight; `tensor` counts tensors in the order they're listed here.
+    fn write_model_with(
+        name: &str,
+        layer_norm_eps: Option<f32>,
+        mut fill: impl FnMut(&str, usize, usize) -> f32,
+    ) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("encoder-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut shapes: Vec<(String, Vec<usize>)> = vec![
+            ("embeddings.word_embeddings.weight".into(), vec![VOCAB, HIDDEN]),
+            ("embeddings.position_embeddings.weight".into(), vec![POSITIONS, HIDDEN]),
+            ("embeddings.token_type_embeddings.weight".into(), vec![2, HIDDEN]),
+            ("embeddings.LayerNorm.weight".into(), vec![HIDDEN]),
+            ("embeddings.LayerNorm.bias".into(), vec![HIDDEN]),
+        ];
+        for layer in 0..2 {
+            let prefix = format!("encoder.layer.{}", layer);
+            for (name, outputs, inputs) in [
+                ("attention.self.query", HIDDEN, HIDDEN),
+                ("attention.self.key", HIDDEN, HIDDEN),
+                ("attention.self.value", HIDDEN, HIDDEN),
+                ("attention.output.dense", HIDDEN, HIDDEN),
+                ("intermediate.dense", INTERMEDIATE, HIDDEN),
+                ("output.dense", HIDDEN, INTERMEDIATE),
+            ] {
+                shapes.push((format!("{}.{}.weight", prefix, name), vec![outputs, inputs]));
+                shapes.push((format!("{}.{}.bias", prefix, name), vec![outputs]));
+            }
+            for name in ["attention.output.LayerNorm", "output.LayerNorm"] {
+                shapes.push((format!("{}.{}.weight", prefix, name), vec![HIDDEN]));
+                shapes.push((format!("{}.{}.bias", prefix, name), vec![HIDDEN]));
+            }
+        }
+        let data: Vec<Vec<u8>> = shapes
+            .iter()
+            .enumerate()
+            .map(|(tensor, (name, shape))| {
+                (0..shape.iter().product::<usize>())
+                    .flat_map(|i| fill(name, tensor, i).to_le_bytes())
+                    .collect()
+            })
+            .collect();
+        let views: Vec<(&str, TensorView)> = shapes
+            .iter()
+            .zip(&data)
+            .map(|((name, shape), bytes)| (name.as_str(), TensorView::new(Dtype::F32, shape.clone(), bytes).unwrap()))
+            .collect();
+        fs::write(dir.join("model.safetensors"), safetensors::serialize(views, &None).unwrap()).unwrap();
+
+        let mut config = serde_json::json!({
+            "hidden_size": HIDDEN,
+            "num_hidden_layers": 2,
+            "num_attention_heads": 2,
+            "intermediate_size": INTERMEDIATE,
+            "max_position_embeddings": POSITIONS,
+        });
+        if let Some(eps) = layer_norm_eps {
+            config["layer_norm_eps"] = eps.into();
+        }
+        fs::write(dir.join("config.json"), config.to_string()).unwrap();
+
+        let mut vocab: HashMap<String, usize> =
+            [("[UNK]".to_string(), 0), ("[CLS]".to_string(), 1), ("[SEP]".to_string(), 2)].into();
+        vocab.extend((0..WORDS).map(|i| (format!("w{}", i), i + 3)));
+        let special = |token: &str, id: usize| serde_json::json!({ "id": token, "ids": [id], "tokens": [token] });
+        let tokenizer = serde_json::json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": { "type": "BertPreTokenizer" },
+            "post_processor": {
+                "type": "TemplateProcessing",
+                "single": [
+                    { "SpecialToken": { "id": "[CLS]", "type_id": 0 } },
+                    { "Sequence": { "id": "A", "type_id": 0 } },
+                    { "SpecialToken": { "id": "[SEP]", "type_id": 0 } }
+                ],
+                "pair": [
+                    { "SpecialToken": { "id": "[CLS]", "type_id": 0 } },
+                    { "Sequence": { "id": "A", "type_id": 0 } },
+                    { "SpecialToken": { "id": "[SEP]", "type_id": 0 } },
+                    { "Sequence": { "id": "B", "type_id": 1 } },
+                    { "SpecialToken": { "id": "[SEP]", "type_id": 1 } }
+                ],
+                "special_tokens": { "[CLS]": special("[CLS]", 1), "[SEP]": special("[SEP]", 2) }
+            },
+            "decoder": null,
+            "model": { "type": "WordLevel", "vocab": vocab, "unk_token": "[UNK]" }
+        });
+        fs::write(dir.join("tokenizer.json"), tokenizer.to_string()).unwrap();
+        dir
+    }
+
+    fn load(dir: &Path, pooling: Pooling, max_seq_len: Option<usize>) -> EncoderEmbedding {
+        let path = |file: &str| dir.join(file).to_str().unwrap().to_string();
+        EncoderEmbedding::load(&EncoderConfig {
+            weights_path: path("model.safetensors"),
+            config_path: path("config.json"),
+            tokenizer_path: path("tokenizer.json"),
+            pooling,
+            max_seq_len,
+            batch_size: Some(2),
+        })
+        .unwrap()
+    }
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5)
+    }
+
+    #[test]
+    fn embeds_unit_vectors_of_hidden_size() {
+        let dir = write_model("unit");
+        let model = load(&dir, Pooling::Mean, None);
+        assert_eq!(model.dims(), HIDDEN);
+        let vector = model.get_embedding("w1 w2 w3").unwrap();
+        assert_eq!(vector.len(), HIDDEN);
+        assert!((dot(&vector, &vector).sqrt() - 1.0).abs() < 1e-5);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cls_and_mean_pooling_differ() {
+        let dir = write_model("pooling");
+        let cls = load(&dir, Pooling::Cls, None).get_embedding("w4 w5 w6").unwrap();
+        let mean = load(&dir, Pooling::Mean, None).get_embedding("w4 w5 w6").unwrap();
+        assert!(!close(&cls, &mean));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncates_to_max_seq_len() {
+        let dir = write_model("truncate");
+        // [CLS] and [SEP] count towards the limit, leaving room for three words.
+        let model = load(&dir, Pooling::Mean, Some(5));
+        let long = model.get_embedding("w0 w1 w2 w3 w4 w5 w6").unwrap();
+        assert!(close(&long, &model.get_embedding("w0 w1 w2").unwrap()));
+        let untruncated = load(&dir, Pooling::Mean, None).get_embedding("w0 w1 w2 w3 w4 w5 w6").unwrap();
+        assert!(!close(&long, &untruncated));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn batch_matches_single() {
+        let dir = write_model("batch");
+        let model = load(&dir, Pooling::Mean, None);
+        let texts: Vec<String> =
+            ["w0", "w1 w2", "w3 w4 w5", "w9 w8 w7 w6", "w2 w2"].iter().map(|x| x.to_string()).collect();
+        let batch = model.get_embeddings(&texts);
+        assert_eq!(batch.len(), texts.len());
+        for (text, vector) in texts.iter().zip(batch) {
+            assert!(close(&vector.unwrap(), &model.get_embedding(text).unwrap()));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Expected values come from a float64 port of transformers' BertModel
+    /// forward pass (embeddings, post-norm layers, erf GELU) with
+    /// sentence-transformers pooling, run on these same weights.
+    #[test]
+    fn matches_reference_outputs() {
+        let dir = write_model_with("golden", Some(0.01), |name, tensor, i| {
+            let wave = ((tensor * 31 + i) as f64 * 0.7).sin();
+            let value = match name.rsplit_once("LayerNorm.") {
+                Some((_, "weight")) => 1.0 + 0.2 * wave,
+                Some(_) => 0.1 * wave,
+                None => 0.5 * wave,
+            };
+            value as f32
+        });
+        let expected_mean = [
+            [0.469363, -0.123249, -0.402591, 0.156801, 0.562751, 0.066092, -0.461341, -0.209642],
+            // zz isn't in the vocabulary and goes in as [UNK].
+            [0.529577, -0.113887, -0.442906, 0.093639, 0.515124, 0.047482, -0.454361, -0.166109],
+        ];
+        let expected_cls = [0.449127, -0.158217, -0.368165, 0.233979, 0.564189, 0.006291, -0.483852, -0.174640];
+        let near = |got: &[f32], expected: &[f32]| {
+            assert_eq!(got.len(), expected.len());
+            for (got, expected) in got.iter().zip(expected) {
+                assert!((got - expected).abs() < 1e-4, "{:?} != {:?}", got, expected);
+            }
+        };
+        let texts = vec!["w1 w4 w7".to_string(), "w2 zz".to_string()];
+        let mean = load(&dir, Pooling::Mean, None).get_embeddings(&texts);
+        for (got, expected) in mean.iter().zip(&expected_mean) {
+            near(got.as_ref().unwrap(), expected);
+        }
+        near(&load(&dir, Pooling::Cls, None).get_embedding("w1 w4 w7").unwrap(), &expected_cls);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,23 +1,4 @@
-use tch::Tensor;
-
-pub(crate) fn binary_search(
-    indexes: &Vec<Tensor>,
-    query: &Tensor,
-    compare: &impl Fn(&Tensor, &Tensor) -> bool,
-) -> usize {
-    let mut low: usize = 0;
-    let mut high: usize = indexes.len();
-    let mut mid: usize;
-    while low < high {
-        mid = low + ((high - low) / 2);
-        if compare(&indexes[mid], query) {
-            low = mid
-        } else {
-            high = mid;
-        }
-    }
-    low
-}
+use tch::{Device, Tensor};
 
 pub(crate) fn cosine_similarity_rust_float(l: &Tensor, r: &Tensor) -> f32 {
     let dot_product = l.dot(r);
@@ -27,6 +8,14 @@ pub(crate) fn cosine_similarity_rust_float(l: &Tensor, r: &Tensor) -> f32 {
     similarity.double_value(&[]) as f32
 }
 
+pub(crate) fn vec_to_tensor(vector: &[f32]) -> Tensor {
+    Tensor::from_slice(vector).to_device(Device::Cuda(0))
+}
+
+pub(crate) fn tensor_to_vec(tensor: &Tensor) -> Vec<f32> {
+    Vec::<f32>::try_from(tensor.to_device(Device::Cpu)).unwrap()
+}
+
 pub(crate) fn binary_search_floats(array: &Vec<f32>, query: &f32) -> usize {
     let mut low = 0;
     let mut high = array.len();
@@ -36,14 +25,79 @@ pub(crate) fn binary_search_floats(array: &Vec<f32>, query: &f32) -> usize {
         if array[mid] < *query {
             high = mid;
         } else {
-            low = mid;
+            low = mid + 1;
         }
     }
     low
 }
 
-pub(crate) fn compare(zero: &Tensor) -> impl Fn(&Tensor, &Tensor) -> bool {
-    move |l: &Tensor, r: &Tensor| {
-        cosine_similarity_rust_float(l, zero) < cosine_similarity_rust_float(r, zero)
+/// Lowercased alphanumeric runs, shared by the full-text payload index and filters.
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+/// Byte ranges of the sentences in `text`, split after `.`, `!` or `?`
+/// followed by whitespace, with surrounding whitespace trimmed off.
+pub(crate) fn sentence_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_break = matches!(c, '.' | '!' | '?') && chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if at_break {
+            spans.push((start, i + c.len_utf8()));
+            start = i + c.len_utf8();
+        }
+    }
+    spans.push((start, text.len()));
+    spans
+        .into_iter()
+        .filter_map(|(start, end)| {
+            let sentence = &text[start..end];
+            let trimmed = sentence.trim_start();
+            let start = start + (sentence.len() - trimmed.len());
+            let end = start + trimmed.trim_end().len();
+            (end > start).then_some((start, end))
+        })
+        .collect()
+}
+
+/// Keeps `index_vec`/`dist_vec` as the best `k` hits seen so far, highest first.
+pub(crate) fn insert_top_k(
+    index_vec: &mut Vec<usize>,
+    dist_vec: &mut Vec<f32>,
+    idx: usize,
+    dist: f32,
+    k: usize,
+) {
+    let loc = binary_search_floats(dist_vec, &dist);
+    index_vec.insert(loc, idx);
+    dist_vec.insert(loc, dist);
+    if dist_vec.len() > k {
+        dist_vec.pop();
+        index_vec.pop();
     }
 }
+
+/// Offset of the blank line ending the HTTP headers.
+pub(crate) fn header_end(request: &[u8]) -> Option<usize> {
+    request.windows(4).position(|w| w == b"\r\n\r\n")
+}
+
+pub(crate) fn content_length(head: &[u8]) -> Option<usize> {
+    String::from_utf8_lossy(head)
+        .to_lowercase()
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length:").map(|x| x.trim().to_string()))
+        .and_then(|x| x.parse::<usize>().ok())
+}
+
+pub(crate) fn is_chunked(head: &[u8]) -> bool {
+    String::from_utf8_lossy(head)
+        .to_lowercase()
+        .lines()
+        .any(|line| line.strip_prefix("transfer-encoding:").is_some_and(|x| x.contains("chunked")))
+}
@@ -0,0 +1,391 @@
+use std::collections::VecDeque;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+use crate::db_interface::DbCalls;
+use crate::helpers::{content_length, header_end, is_chunked};
+use crate::payload::Payload;
+use crate::sparse::SparseVector;
+use crate::chunking::ChunkOptions;
+use crate::types::{IngestReport, LineError, Response};
+
+/// Lines sent to the db per IngestBatch call.
+const INGEST_BATCH_SIZE: usize = 256;
+/// Batches the db may still be working on while we keep reading the socket.
+const MAX_IN_FLIGHT: usize = 2;
+const MAX_LINE_BYTES: usize = 1 << 20;
+/// Longest chunk-size line (size plus extensions) we wait for.
+const MAX_CHUNK_HEADER_BYTES: usize = 1024;
+
+/// One NDJSON line: text to store, plus a precomputed vector if the caller
+/// already has one.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct IngestLine {
+    pub entry: String,
+    pub vector: Option<Vec<f32>>,
+    #[serde(default)]
+    pub payload: Payload,
+    pub sparse: Option<SparseVector>,
+    /// Vectors for named vector spaces; only used on lines with `vector`.
+    #[serde(default)]
+    pub named: HashMap<String, Vec<f32>>,
+    /// Split `entry` into overlapping chunks; only used on lines without `vector`.
+    pub chunk: Option<ChunkOptions>,
+    /// `model_id` of the model that produced `vector`.
+    pub model: Option<String>,
+}
+
+/// Where the request body ends: a byte count, or chunked framing that
+/// gets stripped as the bytes arrive.
+enum Body {
+    Length(usize),
+    Chunked(Dechunker),
+}
+
+#[derive(Default)]
+struct Dechunker {
+    /// Raw bytes not decoded yet, at most a chunk-size line or a CRLF.
+    raw: Vec<u8>,
+    /// Data bytes left in the current chunk.
+    left: usize,
+    /// The current chunk's data is done and its CRLF is next.
+    needs_crlf: bool,
+    /// Saw the zero-size last chunk; trailers are ignored.
+    done: bool,
+}
+
+impl Body {
+    fn new(head: &[u8]) -> Result<Self, String> {
+        if is_chunked(head) {
+            return Ok(Body::Chunked(Dechunker::default()));
+        }
+        // A request body only ends when its length says so; waiting for the
+        // client to close could wait forever.
+        content_length(head)
+            .map(Body::Length)
+            .ok_or_else(|| "Ingest needs a Content-Length or a chunked body".to_string())
+    }
+
+    /// Appends the body bytes in `input` to `out`.
+    fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+        match self {
+            Body::Length(remaining) => {
+                let n = input.len().min(*remaining);
+                out.extend_from_slice(&input[..n]);
+                *remaining -= n;
+                Ok(())
+            }
+            Body::Chunked(dechunker) => dechunker.feed(input, out),
+        }
+    }
+
+    fn finished(&self) -> bool {
+        match self {
+            Body::Length(remaining) => *remaining == 0,
+            Body::Chunked(dechunker) => dechunker.done,
+        }
+    }
+}
+
+impl Dechunker {
+    fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+        self.raw.extend_from_slice(input);
+        let mut pos = 0;
+        while !self.done {
+            if self.left > 0 {
+                let n = self.left.min(self.raw.len() - pos);
+                out.extend_from_slice(&self.raw[pos..pos + n]);
+                pos += n;
+                self.left -= n;
+                if self.left > 0 {
+                    break;
+                }
+                self.needs_crlf = true;
+            }
+            if self.needs_crlf {
+                if self.raw.len() - pos < 2 {
+                    break;
+                }
+                if &self.raw[pos..pos + 2] != b"\r\n" {
+                    return Err("Chunk data not followed by CRLF".to_string());
+                }
+                pos += 2;
+                self.needs_crlf = false;
+            }
+            let Some(end) = self.raw[pos..].windows(2).position(|w| w == b"\r\n") else {
+                if self.raw.len() - pos > MAX_CHUNK_HEADER_BYTES {
+                    return Err("Chunk size line too long".to_string());
+                }
+                break;
+            };
+            let line = String::from_utf8_lossy(&self.raw[pos..pos + end]);
+            let size = line.split(';').next().unwrap_or("").trim();
+            self.left = usize::from_str_radix(size, 16).map_err(|_| format!("Invalid chunk size {:?}", size))?;
+            pos += end + 2;
+            self.done = self.left == 0;
+        }
+        self.raw.drain(..pos);
+        Ok(())
+    }
+}
+
+struct PendingBatch {
+    line_numbers: Vec<usize>,
+    receiver: oneshot::Receiver<Response>,
+}
+
+struct Ingest<'a> {
+    db_address: &'a Sender<DbCalls>,
+    batch: Vec<IngestLine>,
+    batch_lines: Vec<usize>,
+    pending: VecDeque<PendingBatch>,
+    report: IngestReport,
+}
+
+/// Streams an NDJSON body into the db. Memory stays bounded by one partial
+/// line plus MAX_IN_FLIGHT batches no matter how big the upload is.
+pub(crate) async fn handle_ingest(socket: &mut TcpStream, head: Vec<u8>, db_address: &Sender<DbCalls>) -> Response {
+    let body_start = match header_end(&head) {
+        Some(x) => x + 4,
+        None => return Response::Error("No body in ingest request".to_string()),
+    };
+    let mut body = match Body::new(&head[..body_start]) {
+        Ok(body) => body,
+        Err(e) => return Response::Error(e),
+    };
+    let mut partial = vec![];
+    if let Err(e) = body.feed(&head[body_start..], &mut partial) {
+        return Response::Error(e);
+    }
+    let mut ingest = Ingest {
+        db_address,
+        batch: Vec::with_capacity(INGEST_BATCH_SIZE),
+        batch_lines: Vec::with_capacity(INGEST_BATCH_SIZE),
+        pending: VecDeque::new(),
+        report: IngestReport::default(),
+    };
+    let mut buf = [0; 8192];
+    let mut skipping = false;
+    let mut truncated = false;
+    loop {
+        while let Some(pos) = partial.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = partial.drain(..=pos).collect();
+            if skipping {
+                // Tail of a line that was already reported as too long.
+                skipping = false;
+                continue;
+            }
+            ingest.add_line(&line).await;
+        }
+        if partial.len() > MAX_LINE_BYTES {
+            if !skipping {
+                ingest.report.lines += 1;
+                ingest.report.errors.push(LineError {
+                    line: ingest.report.lines,
+                    error: format!("Line longer than {} bytes", MAX_LINE_BYTES),
+                });
+                skipping = true;
+            }
+            partial.clear();
+        }
+        if body.finished() {
+            break;
+        }
+        let n = match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => {
+                truncated = true;
+                break;
+            }
+            Ok(n) => n,
+        };
+        if let Err(error) = body.feed(&buf[..n], &mut partial) {
+            // Nothing past a framing error can be trusted, not even the line in progress.
+            ingest.report.errors.push(LineError { line: ingest.report.lines + 1, error });
+            partial.clear();
+            break;
+        }
+    }
+    // A cut-off upload's last line is most likely cut off too, so it's dropped.
+    if !partial.is_empty() && !skipping && !truncated {
+        ingest.add_line(&partial).await;
+    }
+    ingest.dispatch().await;
+    while let Some(pending) = ingest.pending.pop_front() {
+        ingest.collect(pending).await;
+    }
+    if truncated {
+        return Response::Error(format!(
+            "Upload ended before the body did; stopped after line {} ({} inserted, {} errors)",
+            ingest.report.lines,
+            ingest.report.inserted,
+            ingest.report.errors.len()
+        ));
+    }
+    Response::IngestReport(ingest.report)
+}
+
+impl Ingest<'_> {
+    async fn add_line(&mut self, line: &[u8]) {
+        self.report.lines += 1;
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            return;
+        }
+        match serde_json::from_slice::<IngestLine>(line) {
+            Ok(parsed) => {
+                self.batch.push(parsed);
+                self.batch_lines.push(self.report.lines);
+                if self.batch.len() >= INGEST_BATCH_SIZE {
+                    self.dispatch().await;
+                }
+            }
+            Err(e) => self.report.errors.push(LineError {
+                line: self.report.lines,
+                error: format!("Invalid JSON: {}", e),
+            }),
+        }
+    }
+
+    /// Hands the current batch to the db and only waits on the oldest one
+    /// once MAX_IN_FLIGHT are outstanding, so parsing overlaps embedding.
+    async fn dispatch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let (sender, receiver) = oneshot::channel();
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(INGEST_BATCH_SIZE));
+        let line_numbers = std::mem::replace(&mut self.batch_lines, Vec::with_capacity(INGEST_BATCH_SIZE));
+        self.db_address.send(DbCalls::IngestBatch(batch, sender)).await.unwrap();
+        self.pending.push_back(PendingBatch { line_numbers, receiver });
+        while self.pending.len() > MAX_IN_FLIGHT {
+            let oldest = self.pending.pop_front().unwrap();
+            self.collect(oldest).await;
+        }
+    }
+
+    async fn collect(&mut self, pending: PendingBatch) {
+        match pending.receiver.await.unwrap() {
+            Response::InsertResults(results) => {
+                for result in results {
+                    match result.error {
+                        Some(error) => self.report.errors.push(LineError {
+                            line: pending.line_numbers[result.index],
+                            error,
+                        }),
+                        None if result.duplicate => self.report.duplicates += 1,
+                        None => self.report.inserted += 1,
+                    }
+                }
+            }
+            other => {
+                let error = match other {
+                    Response::Error(e) => e,
+                    _ => "Invalid Response from DB".to_string(),
+                };
+                for line in pending.line_numbers {
+                    self.report.errors.push(LineError { line, error: error.clone() });
+                }
+            }
+        }
+        println!(
+            "Ingest: {} lines read, {} inserted, {} duplicates, {} errors",
+            self.report.lines,
+            self.report.inserted,
+            self.report.duplicates,
+            self.report.errors.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNKED: &[u8] = b"7\r\n{\"a\":1}\r\n3;ext=1\r\n\n{}\r\n0\r\n\r\n";
+
+    #[test]
+    fn dechunks_whole_body() {
+        let mut body = Body::new(b"POST /ingest HTTP/1.1\r\nTransfer-Encoding: chunked").unwrap();
+        let mut out = vec![];
+        body.feed(CHUNKED, &mut out).unwrap();
+        assert!(body.finished());
+        assert_eq!(out, b"{\"a\":1}\n{}");
+    }
+
+    #[test]
+    fn dechunks_one_byte_at_a_time() {
+        let mut dechunker = Dechunker::default();
+        let mut out = vec![];
+        for byte in CHUNKED {
+            dechunker.feed(&[*byte], &mut out).unwrap();
+        }
+        assert!(dechunker.done);
+        assert_eq!(out, b"{\"a\":1}\n{}");
+    }
+
+    #[test]
+    fn rejects_bad_framing() {
+        let mut out = vec![];
+        assert!(Dechunker::default().feed(b"zz\r\n", &mut out).is_err());
+        assert!(Dechunker::default().feed(b"2\r\nabcd", &mut out).is_err());
+    }
+
+    /// Feeds `body` to handle_ingest over a real socket, with a stand-in db
+    /// that accepts every line.
+    async fn ingest(request: &'static [u8]) -> Response {
+        use tokio::io::AsyncWriteExt;
+        use tokio::net::TcpListener;
+        use crate::types::InsertResult;
+
+        // The server has already read the head by the time it calls handle_ingest.
+        let end = header_end(request).unwrap() + 4;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut client = TcpStream::connect(address).await.unwrap();
+            client.write_all(&request[end..]).await.unwrap();
+        });
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let (db_address, mut calls) = tokio::sync::mpsc::channel(4);
+        tokio::spawn(async move {
+            while let Some(DbCalls::IngestBatch(lines, sender)) = calls.recv().await {
+                let results = (0..lines.len()).map(|index| InsertResult { index, id: Some(index), duplicate: false, error: None });
+                let _ = sender.send(Response::InsertResults(results.collect()));
+            }
+        });
+        handle_ingest(&mut socket, request[..end].to_vec(), &db_address).await
+    }
+
+    #[tokio::test]
+    async fn reads_up_to_content_length() {
+        let response = ingest(b"POST /ingest HTTP/1.1\r\nContent-Length: 27\r\n\r\n{\"entry\":\"a\"}\n{\"entry\":\"b\"}").await;
+        match response {
+            Response::IngestReport(report) => {
+                assert_eq!(report.lines, 2);
+                assert_eq!(report.inserted, 2);
+            }
+            other => panic!("Expected a report, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_a_truncated_upload() {
+        let response = ingest(b"POST /ingest HTTP/1.1\r\nContent-Length: 100\r\n\r\n{\"entry\":\"a\"}\n{\"entry\":").await;
+        match response {
+            Response::Error(e) => assert!(e.contains("stopped after line 1 (1 inserted"), "{}", e),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn needs_a_length_or_chunks() {
+        assert!(Body::new(b"POST /ingest HTTP/1.1\r\nHost: x").is_err());
+        let mut body = Body::new(b"POST /ingest HTTP/1.1\r\nContent-Length: 3").unwrap();
+        let mut out = vec![];
+        body.feed(b"{}\nextra", &mut out).unwrap();
+        assert!(body.finished());
+        assert_eq!(out, b"{}\n");
+    }
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+/// Which side of retrieval a text is embedded for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum EmbedRole {
+    Query,
+    Document,
+}
+
+/// Instructions asymmetric models expect around their input, e.g.
+/// `"query: {text}"` and `"passage: {text}"`. A template without `{text}`
+/// is used as a prefix. Applied after the text pipeline.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct InstructionTemplates {
+    pub query: Option<String>,
+    pub document: Option<String>,
+}
+
+impl InstructionTemplates {
+    pub fn apply(&self, role: EmbedRole, text: &str) -> String {
+        let template = match role {
+            EmbedRole::Query => &self.query,
+            EmbedRole::Document => &self.document,
+        };
+        match template {
+            Some(template) if template.contains("{text}") => template.replace("{text}", text),
+            Some(prefix) => format!("{}{}", prefix, text),
+            None => text.to_string(),
+        }
+    }
+}
@@ -1,57 +1,93 @@
 use libc::{c_char, c_float};
 use std::ffi::{c_void, CString};
-use tch::{Device, Tensor};
+use crate::embedder::{file_id, Embedder};
 
 #[link(name = "llamafile_embedding_lib")]
 extern "C" {
     fn create_embedding(model_path: *const c_char) -> *mut c_void;
     fn destroy_embedding(embedding: *mut c_void);
+    fn get_embedding_dims(embedding: *mut c_void) -> usize;
+    /// Returns exactly `get_embedding_dims` floats the caller must hand back
+    /// to `free_embedding`, or null when the model gave anything else.
     fn get_single_embedding(embedding: *mut c_void, text: *const c_char) -> *mut c_float;
+    /// Same as `get_single_embedding`, `num_texts` rows back to back.
     fn get_multiple_embeddings(
         embedding: *mut c_void,
         texts: *const *const c_char,
         num_texts: usize,
     ) -> *mut c_float;
+    fn free_embedding(buffer: *mut c_float);
 }
 
 pub struct LlamafileEmbedding {
     ptr: *mut c_void,
+    dims: usize,
+    model_id: String,
 }
 
+// Moves between threads but is never shared: a re-embed job builds and uses
+// its embedder on a blocking thread, then hands it over to the db task, which
+// is the only user from then on. Deliberately not Sync.
+unsafe impl Send for LlamafileEmbedding {}
+
 impl LlamafileEmbedding {
-    pub fn new(model_path: &str) -> Self {
-        let c_model_path = CString::new(model_path).unwrap();
+    /// Loads the model and asks the library how wide its vectors are.
+    pub fn new(model_path: &str) -> Result<Self, String> {
+        let c_model_path = CString::new(model_path).map_err(|_| "Model path contains a NUL byte".to_string())?;
         let ptr = unsafe { create_embedding(c_model_path.as_ptr()) };
-        LlamafileEmbedding { ptr }
+        if ptr.is_null() {
+            return Err(format!("Can't load llamafile model {}", model_path));
+        }
+        let dims = unsafe { get_embedding_dims(ptr) };
+        // Built before the check so Drop releases the handle either way.
+        let model_id = format!("llamafile:{}:{}", file_id(model_path), dims);
+        let model = LlamafileEmbedding { ptr, dims, model_id };
+        if dims == 0 {
+            return Err(format!("Llamafile model {} reports no dimensions", model_path));
+        }
+        Ok(model)
     }
 
-    pub fn get_embedding(&self, text: &str) -> Tensor {
-        let c_text = CString::new(text).unwrap();
-        let embedding_ptr = unsafe { get_single_embedding(self.ptr, c_text.as_ptr()) };
-        let vec = unsafe { Vec::from_raw_parts(embedding_ptr, 768, 768) };
-        Tensor::from_slice(&vec).to_device(Device::Cuda(0))
+    /// Copies `len` floats out of a library buffer and frees it. The library
+    /// checks the length before handing a buffer over and sends null if the
+    /// model's output was a different size, so a non-null buffer is `len` long.
+    fn take_buffer(buffer: *mut c_float, len: usize) -> Result<Vec<f32>, String> {
+        if buffer.is_null() {
+            return Err(format!("Llamafile returned no embedding of {} floats", len));
+        }
+        let values = unsafe { std::slice::from_raw_parts(buffer, len) }.to_vec();
+        unsafe { free_embedding(buffer) };
+        Ok(values)
     }
+}
 
-    fn get_embeddings(&self, texts: &[String]) -> Tensor {
-        let c_texts: Vec<*const c_char> = texts
-            .iter()
-            .map(|s| CString::new(s.as_str()).unwrap().into_raw())
-            .collect();
-        let embeddings_ptr =
-            unsafe { get_multiple_embeddings(self.ptr, c_texts.as_ptr(), texts.len()) };
-        let embeddings =
-            unsafe { Vec::from_raw_parts(embeddings_ptr, texts.len() * 768, texts.len() * 768) };
+impl Embedder for LlamafileEmbedding {
+    fn dims(&self) -> usize {
+        self.dims
+    }
 
-        // Clean up CStrings
-        for &ptr in &c_texts {
-            unsafe {
-                let _ = CString::from_raw(ptr as *mut c_char);
-            }
-        }
+    fn model_id(&self) -> String {
+        self.model_id.clone()
+    }
+
+    fn get_embedding(&self, text: &str) -> Result<Vec<f32>, String> {
+        let c_text = CString::new(text).map_err(|_| "Text contains a NUL byte".to_string())?;
+        let embedding_ptr = unsafe { get_single_embedding(self.ptr, c_text.as_ptr()) };
+        Self::take_buffer(embedding_ptr, self.dims)
+    }
 
-        Tensor::from_slice(&embeddings)
-            .view([-1, 768])
-            .to_device(Device::Cuda(0))
+    fn get_embeddings(&self, texts: &[String]) -> Vec<Result<Vec<f32>, String>> {
+        let c_texts = match texts.iter().map(|s| CString::new(s.as_str())).collect::<Result<Vec<CString>, _>>() {
+            Ok(c_texts) => c_texts,
+            // The library takes the batch or nothing, so fail just the texts with a NUL.
+            Err(_) => return texts.iter().map(|text| self.get_embedding(text)).collect(),
+        };
+        let c_ptrs: Vec<*const c_char> = c_texts.iter().map(|s| s.as_ptr()).collect();
+        let embeddings_ptr = unsafe { get_multiple_embeddings(self.ptr, c_ptrs.as_ptr(), texts.len()) };
+        match Self::take_buffer(embeddings_ptr, texts.len() * self.dims) {
+            Ok(embeddings) => embeddings.chunks(self.dims).map(|x| Ok(x.to_vec())).collect(),
+            Err(e) => texts.iter().map(|_| Err(e.clone())).collect(),
+        }
     }
 }
 
@@ -64,16 +100,16 @@ impl Drop for LlamafileEmbedding {
 // Example Use
 
 // fn main() -> Result<(), Box<dyn std::error::Error>> {
-//     let model = LlamafileEmbedding::new("/path/to/your/llamafile/model");
+//     let model = LlamafileEmbedding::new("/path/to/your/llamafile/model")?;
 
-//     let embedding = model.get_embedding("Hello, world!");
-//     println!("Single embedding shape: {:?}", embedding.size());
-//     println!("Single embedding (first 5 values): {:?}", embedding.slice(0, 0, 5, 1).to_vec());
+//     let embedding = model.get_embedding("Hello, world!")?;
+//     println!("Single embedding length: {}", embedding.len());
+//     println!("Single embedding (first 5 values): {:?}", &embedding[..5]);
 
 //     let texts = vec!["Hello, world!".to_string(), "This is a test.".to_string()];
-//     let embeddings = model.get_embeddings(&texts);
-//     println!("Embeddings tensor shape: {:?}", embeddings.size());
-//     println!("First embedding (first 5 values): {:?}", embeddings.slice(0, 0, 1, 1).slice(1, 0, 5, 1).to_vec());
+//     let embeddings: Vec<Vec<f32>> = model.get_embeddings(&texts).into_iter().collect::<Result<_, _>>()?;
+//     println!("Number of embeddings: {}", embeddings.len());
+//     println!("First embedding (first 5 values): {:?}", &embeddings[0][..5]);
 
 //     Ok(())
 // }
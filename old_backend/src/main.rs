@@ -1,9 +1,29 @@
+mod bm25;
+mod chunking;
 mod helpers;
 mod llama_embedding;
+mod multi_vector;
 mod node_interface;
 mod types;
 mod vector_db;
 mod db_interface;
+mod dedupe;
+mod collection;
+mod embedder;
+mod embedding_cache;
+mod encoder_embedding;
+mod ingest;
+mod instructions;
+mod payload;
+mod payload_index;
+mod query_planner;
+mod reembed;
+mod schedule;
+mod vector_space;
+mod weights;
+mod sparse;
+mod static_embedding;
+mod text_pipeline;
 
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
@@ -11,13 +31,17 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use crate::db_interface::{db_interface, DbCalls};
+use crate::db_interface::{
+    db_interface, handle_batch_insert, handle_batch_search, handle_collection_info, handle_configure_embedding_cache, handle_create_index, handle_create_vector_space, handle_delete, handle_embedding_cache_stats, handle_explain, handle_find, handle_get, handle_group_search,
+    handle_insert, handle_insert_multi_vector, handle_insert_vector, handle_range_search, handle_recommend, handle_reembed, handle_search, handle_search_multi_vector, handle_search_sparse, handle_search_vector, handle_set_dedupe_policy, handle_set_instructions, handle_set_named_vector, handle_set_text_pipeline, handle_update_payload, handle_update_sparse, DbCalls,
+};
 use tokio::sync::mpsc::{Receiver, Sender};
 use crate::db_interface::DbCalls::Kill;
+use crate::helpers::{content_length, header_end};
+use crate::ingest::handle_ingest;
 use crate::types::Response;
 
 const LLAMAFILE_PATH: &str = "LLAMAFILE";
-const NUM_DIMS: usize = 4096;
 
 
 
@@ -25,7 +49,13 @@ const NUM_DIMS: usize = 4096;
 async fn main()  {
     // Bind the listener to the address
     let listener = TcpListener::bind("0.0.0.0:8080").await.unwrap();
-    let (db_process, db_address) = db_interface(LLAMAFILE_PATH, NUM_DIMS);
+    let (db_process, db_address) = match db_interface(LLAMAFILE_PATH) {
+        Ok(db) => db,
+        Err(e) => {
+            println!("Can't start the database: {}", e);
+            return;
+        }
+    };
     let loop_invariant = Arc::new(AtomicBool::new(true));
     while loop_invariant.load(Ordering::Relaxed) {
         // The second item contains the IP and port of the new connection.
@@ -39,7 +69,7 @@ async fn main()  {
         });
     }
     println!("Server Shutting Down");
-    db_address.send(Kill).unwrap();
+    db_address.send(Kill).await.unwrap();
     db_process.await.unwrap();
 }
 
@@ -48,14 +78,73 @@ async fn process(
     loop_invariant: Arc<AtomicBool>,
     db_address: Sender<DbCalls>
 ) {
-    let mut buf = [0; 8192];
-    let n = socket.read(&mut buf).await.unwrap();
-    let request = str::from_utf8(&buf[..n]).unwrap();
-
-    let response = if request.starts_with("POST /insert") {
+    let mut buf = read_head(&mut socket).await;
+    if buf.starts_with(b"POST /ingest") {
+        // Streamed straight off the socket instead of buffering the whole body.
+        let response = handle_ingest(&mut socket, buf, &db_address).await;
+        let response_json = serde_json::to_string(&response).unwrap();
+        socket.write_all(response_json.as_bytes()).await.unwrap();
+        return;
+    }
+    read_body(&mut socket, &mut buf).await;
+    let request = str::from_utf8(&buf).unwrap();
 
+    let response = if request.starts_with("POST /insert_batch") {
+        handle_batch_insert(request, &db_address).await
+    } else if request.starts_with("POST /insert_multi_vector") {
+        handle_insert_multi_vector(request, &db_address).await
+    } else if request.starts_with("POST /insert_vector") {
+        handle_insert_vector(request, &db_address).await
+    } else if request.starts_with("POST /insert") {
+        handle_insert(request, db_address.clone()).await
     } else if request.starts_with("GET /get") {
-
+        handle_get(request, &db_address).await
+    } else if request.starts_with("POST /search_batch") {
+        handle_batch_search(request, &db_address).await
+    } else if request.starts_with("POST /search_groups") {
+        handle_group_search(request, &db_address).await
+    } else if request.starts_with("POST /search_multi_vector") {
+        handle_search_multi_vector(request, &db_address).await
+    } else if request.starts_with("POST /search_sparse") {
+        handle_search_sparse(request, &db_address).await
+    } else if request.starts_with("POST /search_vector") {
+        handle_search_vector(request, &db_address).await
+    } else if request.starts_with("POST /search") {
+        handle_search(request, &db_address).await
+    } else if request.starts_with("POST /range_search") {
+        handle_range_search(request, &db_address, &mut socket).await
+    } else if request.starts_with("POST /recommend") {
+        handle_recommend(request, &db_address).await
+    } else if request.starts_with("POST /update_sparse") {
+        handle_update_sparse(request, &db_address).await
+    } else if request.starts_with("POST /update_payload") {
+        handle_update_payload(request, &db_address).await
+    } else if request.starts_with("POST /delete") {
+        handle_delete(request, &db_address).await
+    } else if request.starts_with("POST /create_vector_space") {
+        handle_create_vector_space(request, &db_address).await
+    } else if request.starts_with("POST /set_named_vector") {
+        handle_set_named_vector(request, &db_address).await
+    } else if request.starts_with("POST /set_text_pipeline") {
+        handle_set_text_pipeline(request, &db_address).await
+    } else if request.starts_with("POST /set_instructions") {
+        handle_set_instructions(request, &db_address).await
+    } else if request.starts_with("GET /collection") {
+        handle_collection_info(&db_address).await
+    } else if request.starts_with("POST /reembed") {
+        handle_reembed(request, &db_address).await
+    } else if request.starts_with("POST /set_dedupe_policy") {
+        handle_set_dedupe_policy(request, &db_address).await
+    } else if request.starts_with("POST /configure_embedding_cache") {
+        handle_configure_embedding_cache(request, &db_address).await
+    } else if request.starts_with("GET /embedding_cache_stats") {
+        handle_embedding_cache_stats(&db_address).await
+    } else if request.starts_with("POST /create_index") {
+        handle_create_index(request, &db_address).await
+    } else if request.starts_with("POST /find") {
+        handle_find(request, &db_address).await
+    } else if request.starts_with("POST /explain") {
+        handle_explain(request, &db_address).await
     } else if request.starts_with("POST /shutdown") {
         loop_invariant.store(false, Ordering::Relaxed);
         Response::Success
@@ -67,3 +156,35 @@ async fn process(
     socket.write_all(response_json.as_bytes()).await.unwrap();
 }
 
+/// Reads until the end of the headers; whatever part of the body came in
+/// the same reads is left on the end.
+async fn read_head(socket: &mut TcpStream) -> Vec<u8> {
+    let mut request = Vec::new();
+    let mut buf = [0; 8192];
+    while header_end(&request).is_none() {
+        let n = socket.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    request
+}
+
+/// Keeps reading until the body matches Content-Length, so bulk requests
+/// bigger than one socket read arrive whole.
+async fn read_body(socket: &mut TcpStream, request: &mut Vec<u8>) {
+    let header_end = match header_end(request) {
+        Some(x) => x,
+        None => return,
+    };
+    let content_length = content_length(&request[..header_end]).unwrap_or(0);
+    let mut buf = [0; 8192];
+    while request.len() < header_end + 4 + content_length {
+        let n = socket.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::{BTreeSet, HashMap};
+use crate::helpers::{cosine_similarity_rust_float, insert_top_k};
+use tch::Tensor;
+
+/// Bags of vectors (one per sentence or token) for records that opted into
+/// late-interaction scoring. Ids line up with the record ids in `VectorDB`.
+#[derive(Default)]
+pub(crate) struct MultiVectorIndex {
+    /// Every stored vector with the record it belongs to, in insertion order.
+    vectors: Vec<(usize, Tensor)>,
+    /// Positions in `vectors` per record. Removed records drop out of here;
+    /// their tensors stay behind and are skipped.
+    by_record: HashMap<usize, Vec<usize>>,
+}
+
+impl MultiVectorIndex {
+    pub fn add(&mut self, id: usize, vectors: Vec<Tensor>) {
+        let start = self.vectors.len();
+        self.vectors.extend(vectors.into_iter().map(|x| (id, x)));
+        self.by_record.insert(id, (start..self.vectors.len()).collect());
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        self.by_record.remove(&id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_record.is_empty()
+    }
+
+    /// Late interaction: for every query vector take its best match among the
+    /// record's vectors, and sum those.
+    pub fn max_sim(&self, query: &[Tensor], id: usize) -> Option<f32> {
+        let positions = self.by_record.get(&id)?;
+        Some(
+            query
+                .iter()
+                .map(|q| {
+                    positions
+                        .iter()
+                        .map(|x| cosine_similarity_rust_float(q, &self.vectors[*x].1))
+                        .fold(f32::NEG_INFINITY, f32::max)
+                })
+                .sum(),
+        )
+    }
+
+    /// Candidate generation takes the `per_vector` nearest stored vectors for
+    /// each query vector; only records owning one of those get a full MaxSim.
+    pub fn search(&self, query: &[Tensor], k: usize, per_vector: usize, keep: impl Fn(usize) -> bool) -> Vec<(usize, f32)> {
+        let mut nearest: Vec<(Vec<usize>, Vec<f32>)> = vec![(vec![], vec![]); query.len()];
+        for (position, (id, vector)) in self.vectors.iter().enumerate() {
+            if !self.by_record.contains_key(id) || !keep(*id) {
+                continue;
+            }
+            for (q, (index_vec, dist_vec)) in query.iter().zip(nearest.iter_mut()) {
+                let dist = cosine_similarity_rust_float(q, vector);
+                insert_top_k(index_vec, dist_vec, position, dist, per_vector);
+            }
+        }
+        let candidates: BTreeSet<usize> = nearest
+            .into_iter()
+            .flat_map(|(index_vec, _)| index_vec)
+            .map(|position| self.vectors[position].0)
+            .collect();
+        let mut index_vec = vec![];
+        let mut dist_vec = vec![];
+        for id in candidates {
+            if let Some(score) = self.max_sim(query, id) {
+                insert_top_k(&mut index_vec, &mut dist_vec, id, score, k);
+            }
+        }
+        index_vec.into_iter().zip(dist_vec).collect()
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::helpers::tokenize;
+use crate::schedule::{self, Schedule};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum PayloadValue {
+    Keyword(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+    Schedule(Schedule),
+}
+
+pub(crate) type Payload = HashMap<String, PayloadValue>;
+
+impl PayloadValue {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            PayloadValue::Integer(x) => Some(*x as f64),
+            PayloadValue::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            PayloadValue::Keyword(x) | PayloadValue::Text(x) => Some(x.as_str()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum Filter {
+    /// Keeps records whose schedule under `key` is open on the given
+    /// day (Monday=1) at the given HHMM time.
+    OpenAt {
+        key: String,
+        #[serde(deserialize_with = "schedule::day_of_week")]
+        day_of_week: u8,
+        #[serde(deserialize_with = "schedule::hhmm")]
+        time: u16,
+    },
+    Equals { key: String, value: PayloadValue },
+    /// Inclusive numeric range, either bound may be left open.
+    Range { key: String, min: Option<f64>, max: Option<f64> },
+    /// Every token of `text` has to appear in the field.
+    HasText { key: String, text: String },
+    And(Vec<Filter>),
+}
+
+impl Filter {
+    /// The payload field a single-field filter looks at.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Filter::OpenAt { key, .. }
+            | Filter::Equals { key, .. }
+            | Filter::Range { key, .. }
+            | Filter::HasText { key, .. } => Some(key.as_str()),
+            Filter::And(_) => None,
+        }
+    }
+
+    pub fn matches(&self, payload: &Payload) -> bool {
+        match self {
+            Filter::OpenAt { key, day_of_week, time } => match payload.get(key) {
+                Some(PayloadValue::Schedule(schedule)) => schedule.is_open_at(*day_of_week, *time),
+                _ => false,
+            },
+            Filter::Equals { key, value } => payload.get(key) == Some(value),
+            Filter::Range { key, min, max } => match payload.get(key).and_then(|x| x.as_number()) {
+                Some(x) => min.is_none_or(|min| x >= min) && max.is_none_or(|max| x <= max),
+                None => false,
+            },
+            Filter::HasText { key, text } => match payload.get(key).and_then(|x| x.as_text()) {
+                Some(field) => {
+                    let field_tokens = tokenize(field);
+                    tokenize(text).iter().all(|token| field_tokens.contains(token))
+                }
+                None => false,
+            },
+            Filter::And(filters) => filters.iter().all(|f| f.matches(payload)),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::helpers::tokenize;
+use crate::payload::{Filter, PayloadValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum PayloadIndexType {
+    Keyword,
+    Numeric,
+    Bool,
+    FullText,
+}
+
+/// f64 wrapper so numeric payloads can key a BTreeMap.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct NumericKey(f64);
+
+impl PartialEq for NumericKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for NumericKey {}
+
+impl PartialOrd for NumericKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NumericKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Maps the values of one payload field back to the record ids holding them.
+pub(crate) enum PayloadIndex {
+    Keyword(HashMap<String, BTreeSet<usize>>),
+    Numeric(BTreeMap<NumericKey, BTreeSet<usize>>),
+    Bool(HashMap<bool, BTreeSet<usize>>),
+    FullText(HashMap<String, BTreeSet<usize>>),
+}
+
+impl PayloadIndex {
+    pub fn new(index_type: PayloadIndexType) -> Self {
+        match index_type {
+            PayloadIndexType::Keyword => PayloadIndex::Keyword(HashMap::new()),
+            PayloadIndexType::Numeric => PayloadIndex::Numeric(BTreeMap::new()),
+            PayloadIndexType::Bool => PayloadIndex::Bool(HashMap::new()),
+            PayloadIndexType::FullText => PayloadIndex::FullText(HashMap::new()),
+        }
+    }
+
+    pub fn add(&mut self, id: usize, value: &PayloadValue) {
+        match (self, value) {
+            (PayloadIndex::Keyword(map), PayloadValue::Keyword(x)) => {
+                map.entry(x.clone()).or_default().insert(id);
+            }
+            (PayloadIndex::Numeric(map), _) => {
+                if let Some(x) = value.as_number() {
+                    map.entry(NumericKey(x)).or_default().insert(id);
+                }
+            }
+            (PayloadIndex::Bool(map), PayloadValue::Bool(x)) => {
+                map.entry(*x).or_default().insert(id);
+            }
+            (PayloadIndex::FullText(map), _) => {
+                for token in value.as_text().map(tokenize).unwrap_or_default() {
+                    map.entry(token).or_default().insert(id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn remove(&mut self, id: usize, value: &PayloadValue) {
+        match (self, value) {
+            (PayloadIndex::Keyword(map), PayloadValue::Keyword(x)) => remove_id(map, x, id),
+            (PayloadIndex::Numeric(map), _) => {
+                if let Some(x) = value.as_number() {
+                    let key = NumericKey(x);
+                    if let Some(ids) = map.get_mut(&key) {
+                        ids.remove(&id);
+                        if ids.is_empty() {
+                            map.remove(&key);
+                        }
+                    }
+                }
+            }
+            (PayloadIndex::Bool(map), PayloadValue::Bool(x)) => remove_id(map, x, id),
+            (PayloadIndex::FullText(map), _) => {
+                for token in value.as_text().map(tokenize).unwrap_or_default() {
+                    remove_id(map, &token, id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// How many ids `lookup` would return, read off bucket sizes without
+    /// building the set. Full-text takes the rarest token as an upper bound.
+    pub fn estimate(&self, filter: &Filter) -> Option<usize> {
+        match (self, filter) {
+            (PayloadIndex::Keyword(map), Filter::Equals { value: PayloadValue::Keyword(x), .. }) => {
+                Some(map.get(x).map_or(0, |ids| ids.len()))
+            }
+            (PayloadIndex::Numeric(map), Filter::Equals { value, .. }) => {
+                let x = NumericKey(value.as_number()?);
+                Some(map.get(&x).map_or(0, |ids| ids.len()))
+            }
+            (PayloadIndex::Numeric(map), Filter::Range { min, max, .. }) => {
+                let min = NumericKey(min.unwrap_or(f64::NEG_INFINITY));
+                let max = NumericKey(max.unwrap_or(f64::INFINITY));
+                if min > max {
+                    return Some(0);
+                }
+                Some(map.range(min..=max).map(|(_, ids)| ids.len()).sum())
+            }
+            (PayloadIndex::Bool(map), Filter::Equals { value: PayloadValue::Bool(x), .. }) => {
+                Some(map.get(x).map_or(0, |ids| ids.len()))
+            }
+            (PayloadIndex::FullText(map), Filter::HasText { text, .. }) => tokenize(text)
+                .iter()
+                .map(|token| map.get(token).map_or(0, |ids| ids.len()))
+                .min(),
+            _ => None,
+        }
+    }
+
+    /// Ids whose indexed value satisfies `filter`, or None if this index
+    /// can't answer that kind of filter. Callers still run `Filter::matches`
+    /// on the result, so a superset is fine.
+    pub fn lookup(&self, filter: &Filter) -> Option<BTreeSet<usize>> {
+        match (self, filter) {
+            (PayloadIndex::Keyword(map), Filter::Equals { value: PayloadValue::Keyword(x), .. }) => {
+                Some(map.get(x).cloned().unwrap_or_default())
+            }
+            (PayloadIndex::Numeric(map), Filter::Equals { value, .. }) => {
+                let x = NumericKey(value.as_number()?);
+                Some(map.get(&x).cloned().unwrap_or_default())
+            }
+            (PayloadIndex::Numeric(map), Filter::Range { min, max, .. }) => {
+                let min = NumericKey(min.unwrap_or(f64::NEG_INFINITY));
+                let max = NumericKey(max.unwrap_or(f64::INFINITY));
+                if min > max {
+                    return Some(BTreeSet::new());
+                }
+                Some(map.range(min..=max).flat_map(|(_, ids)| ids.iter().copied()).collect())
+            }
+            (PayloadIndex::Bool(map), Filter::Equals { value: PayloadValue::Bool(x), .. }) => {
+                Some(map.get(x).cloned().unwrap_or_default())
+            }
+            (PayloadIndex::FullText(map), Filter::HasText { text, .. }) => {
+                let mut result: Option<BTreeSet<usize>> = None;
+                for token in tokenize(text) {
+                    let ids = map.get(&token).cloned().unwrap_or_default();
+                    result = Some(match result {
+                        Some(acc) => acc.intersection(&ids).copied().collect(),
+                        None => ids,
+                    });
+                }
+                result
+            }
+            _ => None,
+        }
+    }
+}
+
+fn remove_id<K: std::hash::Hash + Eq>(map: &mut HashMap<K, BTreeSet<usize>>, key: &K, id: usize) {
+    if let Some(ids) = map.get_mut(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[usize]) -> Option<BTreeSet<usize>> {
+        Some(ids.iter().copied().collect())
+    }
+
+    fn equals(value: PayloadValue) -> Filter {
+        Filter::Equals { key: "field".to_string(), value }
+    }
+
+    fn range(min: Option<f64>, max: Option<f64>) -> Filter {
+        Filter::Range { key: "field".to_string(), min, max }
+    }
+
+    fn has_text(text: &str) -> Filter {
+        Filter::HasText { key: "field".to_string(), text: text.to_string() }
+    }
+
+    #[test]
+    fn keyword_lookup_and_estimate() {
+        let mut index = PayloadIndex::new(PayloadIndexType::Keyword);
+        index.add(0, &PayloadValue::Keyword("cafe".to_string()));
+        index.add(1, &PayloadValue::Keyword("bar".to_string()));
+        index.add(2, &PayloadValue::Keyword("cafe".to_string()));
+        let cafe = equals(PayloadValue::Keyword("cafe".to_string()));
+        assert_eq!(index.lookup(&cafe), ids(&[0, 2]));
+        assert_eq!(index.estimate(&cafe), Some(2));
+        let missing = equals(PayloadValue::Keyword("pub".to_string()));
+        assert_eq!(index.lookup(&missing), ids(&[]));
+        assert_eq!(index.estimate(&missing), Some(0));
+        // A keyword index can't answer a numeric filter.
+        assert_eq!(index.lookup(&range(Some(0.0), None)), None);
+        assert_eq!(index.estimate(&range(Some(0.0), None)), None);
+    }
+
+    #[test]
+    fn numeric_range_is_inclusive_and_mixes_ints_and_floats() {
+        let mut index = PayloadIndex::new(PayloadIndexType::Numeric);
+        index.add(0, &PayloadValue::Integer(1));
+        index.add(1, &PayloadValue::Float(2.5));
+        index.add(2, &PayloadValue::Integer(4));
+        index.add(3, &PayloadValue::Float(4.0));
+        assert_eq!(index.lookup(&range(Some(2.5), Some(4.0))), ids(&[1, 2, 3]));
+        assert_eq!(index.estimate(&range(Some(2.5), Some(4.0))), Some(3));
+        assert_eq!(index.lookup(&range(None, Some(2.0))), ids(&[0]));
+        assert_eq!(index.lookup(&range(Some(3.0), None)), ids(&[2, 3]));
+        assert_eq!(index.lookup(&range(Some(5.0), Some(1.0))), ids(&[]));
+        assert_eq!(index.estimate(&range(Some(5.0), Some(1.0))), Some(0));
+        assert_eq!(index.lookup(&equals(PayloadValue::Integer(4))), ids(&[2, 3]));
+        assert_eq!(index.estimate(&equals(PayloadValue::Float(4.0))), Some(2));
+    }
+
+    #[test]
+    fn bool_lookup_and_estimate() {
+        let mut index = PayloadIndex::new(PayloadIndexType::Bool);
+        index.add(0, &PayloadValue::Bool(true));
+        index.add(1, &PayloadValue::Bool(false));
+        index.add(2, &PayloadValue::Bool(true));
+        assert_eq!(index.lookup(&equals(PayloadValue::Bool(true))), ids(&[0, 2]));
+        assert_eq!(index.estimate(&equals(PayloadValue::Bool(false))), Some(1));
+    }
+
+    #[test]
+    fn full_text_intersects_tokens_and_estimates_from_the_rarest() {
+        let mut index = PayloadIndex::new(PayloadIndexType::FullText);
+        index.add(0, &PayloadValue::Text("Late night pizza".to_string()));
+        index.add(1, &PayloadValue::Text("pizza and pasta".to_string()));
+        index.add(2, &PayloadValue::Text("Night market".to_string()));
+        assert_eq!(index.lookup(&has_text("pizza")), ids(&[0, 1]));
+        assert_eq!(index.lookup(&has_text("NIGHT pizza")), ids(&[0]));
+        assert_eq!(index.estimate(&has_text("night pizza")), Some(2));
+        assert_eq!(index.estimate(&has_text("night sushi")), Some(0));
+        assert_eq!(index.lookup(&has_text("night sushi")), ids(&[]));
+    }
+
+    #[test]
+    fn removed_ids_drop_out() {
+        let mut index = PayloadIndex::new(PayloadIndexType::Keyword);
+        let cafe = PayloadValue::Keyword("cafe".to_string());
+        index.add(0, &cafe);
+        index.add(1, &cafe);
+        index.remove(0, &cafe);
+        assert_eq!(index.lookup(&equals(cafe.clone())), ids(&[1]));
+        index.remove(1, &cafe);
+        assert_eq!(index.estimate(&equals(cafe)), Some(0));
+
+        let mut index = PayloadIndex::new(PayloadIndexType::Numeric);
+        index.add(0, &PayloadValue::Integer(3));
+        index.remove(0, &PayloadValue::Integer(3));
+        assert_eq!(index.lookup(&range(None, None)), ids(&[]));
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+/// Below this many matching records scoring them directly beats walking every page.
+const BRUTE_FORCE_LIMIT: usize = 1000;
+/// Past this fraction of matches, filtering after an unfiltered search is cheaper.
+const POST_FILTER_SELECTIVITY: f64 = 0.5;
+/// Extra slack on top of 1 / selectivity when oversampling for post-filtering.
+const OVERSAMPLE_MARGIN: f64 = 1.5;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum SearchStrategy {
+    /// Score only the ids the payload indexes returned.
+    BruteForce,
+    /// Walk the pages and check the filter on every record as we go.
+    FilteredScan,
+    /// Unfiltered top `k * oversample`, then drop records failing the filter.
+    PostFilter { oversample: usize },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct QueryPlan {
+    pub strategy: SearchStrategy,
+    pub total_records: usize,
+    /// None when no payload index covers the filter.
+    pub estimated_matches: Option<usize>,
+    pub selectivity: Option<f64>,
+    pub reason: String,
+}
+
+pub(crate) fn plan_search(
+    has_filter: bool,
+    estimated_matches: Option<usize>,
+    total_records: usize,
+    k: usize,
+) -> QueryPlan {
+    let selectivity = estimated_matches.map(|x| x as f64 / total_records.max(1) as f64);
+    let (strategy, reason) = match (has_filter, estimated_matches, selectivity) {
+        (false, _, _) => (SearchStrategy::FilteredScan, "no filter, scanning every page".to_string()),
+        (true, None, _) => (
+            SearchStrategy::FilteredScan,
+            "no payload index covers the filter, checking it during the scan".to_string(),
+        ),
+        (true, Some(matches), _) if matches <= BRUTE_FORCE_LIMIT.max(k) => (
+            SearchStrategy::BruteForce,
+            format!("filter matches ~{} records, scoring them directly", matches),
+        ),
+        (true, Some(_), Some(selectivity)) if selectivity >= POST_FILTER_SELECTIVITY => {
+            let oversample = (OVERSAMPLE_MARGIN / selectivity).ceil() as usize;
+            (
+                SearchStrategy::PostFilter { oversample },
+                format!("filter keeps {:.0}% of records, post-filtering {}x oversampled results", selectivity * 100.0, oversample),
+            )
+        }
+        (true, Some(matches), _) => (
+            SearchStrategy::FilteredScan,
+            format!("filter matches ~{} records, too many to score directly and too few to post-filter", matches),
+        ),
+    };
+    QueryPlan {
+        strategy,
+        total_records,
+        estimated_matches,
+        selectivity,
+        reason,
+    }
+}
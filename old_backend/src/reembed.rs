@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::collection::CollectionConfig;
+use crate::embedder::{Embedder, EmbedderSpec};
+use crate::instructions::{EmbedRole, InstructionTemplates};
+use crate::text_pipeline::TextPipeline;
+use crate::types::DistanceMetric;
+use crate::vector_space::embed_texts;
+use tch::Tensor;
+
+/// Texts handed to the new embedder per call while re-embedding.
+const REEMBED_BATCH_SIZE: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ReembedRequest {
+    /// Llamafile model to move the collection to.
+    pub model_path: Option<String>,
+    /// Or any other embedder.
+    pub embedder: Option<EmbedderSpec>,
+    pub dims: usize,
+}
+
+/// Snapshot of everything the job needs, taken so it can run off the db task
+/// while inserts and searches carry on against the old vectors.
+pub(crate) struct ReembedJob {
+    pub texts: Vec<(usize, String)>,
+    pub pipeline: TextPipeline,
+    pub instructions: InstructionTemplates,
+    pub metric: DistanceMetric,
+    /// Records at or past this id were inserted after the snapshot.
+    pub snapshot_len: usize,
+    /// Texts embedded so far, read by `GET /collection`.
+    pub progress: Arc<AtomicUsize>,
+}
+
+/// New vectors for the snapshot, ready to be swapped in.
+pub(crate) struct ReembedOutput {
+    pub embedder: Box<dyn Embedder + Send>,
+    pub config: CollectionConfig,
+    pub vectors: Vec<(usize, Tensor)>,
+    pub snapshot_len: usize,
+}
+
+/// Blocking: embeds every snapshot text with `embedder`.
+pub(crate) fn run(job: ReembedJob, embedder: Box<dyn Embedder + Send>, dims: usize) -> Result<ReembedOutput, String> {
+    if embedder.dims() != dims {
+        return Err(format!("Asked for {} dimensions but the new embedder produces {}", dims, embedder.dims()));
+    }
+    let config = CollectionConfig {
+        dims,
+        metric: job.metric,
+        model_id: Some(embedder.model_id()),
+    };
+    let mut vectors = Vec::with_capacity(job.texts.len());
+    for batch in job.texts.chunks(REEMBED_BATCH_SIZE) {
+        let texts: Vec<String> = batch
+            .iter()
+            .map(|(_, text)| job.instructions.apply(EmbedRole::Document, &job.pipeline.apply(text)))
+            .collect();
+        for ((id, _), vector) in batch.iter().zip(embed_texts(embedder.as_ref(), &config, &texts, None)) {
+            vectors.push((*id, vector.map_err(|e| format!("Record {}: {}", id, e))?));
+        }
+        job.progress.store(vectors.len(), Ordering::Relaxed);
+        println!("Re-embed: {} / {} records", vectors.len(), job.texts.len());
+    }
+    Ok(ReembedOutput {
+        embedder,
+        config,
+        vectors,
+        snapshot_len: job.snapshot_len,
+    })
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::Error;
+
+const DAYS_PER_WEEK: u8 = 7;
+const LAST_TIME: u16 = 2359;
+
+/// One row of `opening_data`: times are 24 hour HHMM (ie 0930, 2200) and
+/// days run Monday=1 through Sunday=7, same as the scraper writes them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OpeningWindow {
+    #[serde(deserialize_with = "day_of_week")]
+    pub day_of_week: u8,
+    #[serde(deserialize_with = "hhmm")]
+    pub open_time: u16,
+    #[serde(deserialize_with = "hhmm")]
+    pub close_time: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Schedule {
+    pub windows: Vec<OpeningWindow>,
+}
+
+impl OpeningWindow {
+    /// A window that closes at or before it opens runs past midnight, so
+    /// Friday 1800-0200 also covers Saturday 0000-0159.
+    pub fn crosses_midnight(&self) -> bool {
+        self.close_time <= self.open_time
+    }
+
+    pub fn covers(&self, day_of_week: u8, time: u16) -> bool {
+        if !self.crosses_midnight() {
+            return day_of_week == self.day_of_week
+                && self.open_time <= time
+                && time < self.close_time;
+        }
+        (day_of_week == self.day_of_week && time >= self.open_time)
+            || (day_of_week == next_day(self.day_of_week) && time < self.close_time)
+    }
+}
+
+impl Schedule {
+    pub fn is_open_at(&self, day_of_week: u8, time: u16) -> bool {
+        self.windows.iter().any(|w| w.covers(day_of_week, time))
+    }
+}
+
+fn next_day(day_of_week: u8) -> u8 {
+    day_of_week % DAYS_PER_WEEK + 1
+}
+
+/// Serde check for day fields, Monday=1 through Sunday=7.
+pub(crate) fn day_of_week<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let day = u8::deserialize(deserializer)?;
+    if !(1..=DAYS_PER_WEEK).contains(&day) {
+        return Err(D::Error::custom(format!("day_of_week must be between 1 and {}, got {}", DAYS_PER_WEEK, day)));
+    }
+    Ok(day)
+}
+
+/// Serde check for HHMM time fields, 0000 through 2359.
+pub(crate) fn hhmm<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    let time = u16::deserialize(deserializer)?;
+    if time > LAST_TIME || time % 100 >= 60 {
+        return Err(D::Error::custom(format!("time must be HHMM between 0000 and {}, got {}", LAST_TIME, time)));
+    }
+    Ok(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRIDAY: u8 = 5;
+    const SATURDAY: u8 = 6;
+    const SUNDAY: u8 = 7;
+    const MONDAY: u8 = 1;
+
+    fn window(day_of_week: u8, open_time: u16, close_time: u16) -> OpeningWindow {
+        OpeningWindow { day_of_week, open_time, close_time }
+    }
+
+    #[test]
+    fn same_day_window_is_half_open() {
+        let w = window(FRIDAY, 900, 1700);
+        assert!(w.covers(FRIDAY, 900));
+        assert!(w.covers(FRIDAY, 1659));
+        assert!(!w.covers(FRIDAY, 1700));
+        assert!(!w.covers(SATURDAY, 1000));
+    }
+
+    #[test]
+    fn late_window_runs_into_next_day() {
+        let w = window(FRIDAY, 1800, 200);
+        assert!(w.crosses_midnight());
+        assert!(w.covers(FRIDAY, 1800));
+        assert!(w.covers(FRIDAY, 2359));
+        assert!(w.covers(SATURDAY, 0));
+        assert!(w.covers(SATURDAY, 100));
+        assert!(!w.covers(SATURDAY, 200));
+        assert!(!w.covers(FRIDAY, 100));
+        assert!(!w.covers(SATURDAY, 1900));
+    }
+
+    #[test]
+    fn sunday_rolls_over_to_monday() {
+        assert_eq!(next_day(SUNDAY), MONDAY);
+        let w = window(SUNDAY, 2200, 300);
+        assert!(w.covers(SUNDAY, 2300));
+        assert!(w.covers(MONDAY, 230));
+        assert!(!w.covers(MONDAY, 300));
+    }
+
+    #[test]
+    fn open_at_filter_rejects_out_of_range_values() {
+        use crate::payload::Filter;
+        let parse = |day: u32, time: u32| {
+            serde_json::from_str::<Filter>(&format!(
+                r#"{{"OpenAt": {{"key": "hours", "day_of_week": {}, "time": {}}}}}"#,
+                day, time
+            ))
+        };
+        assert!(parse(MONDAY as u32, 0).is_ok());
+        assert!(parse(SUNDAY as u32, 2359).is_ok());
+        assert!(parse(0, 1200).is_err());
+        assert!(parse(8, 1200).is_err());
+        assert!(parse(FRIDAY as u32, 2400).is_err());
+        assert!(parse(FRIDAY as u32, 1260).is_err());
+    }
+
+    #[test]
+    fn stored_schedule_rejects_out_of_range_values() {
+        use crate::payload::PayloadValue;
+        let parse = |day: u32, open: u32, close: u32| {
+            serde_json::from_str::<PayloadValue>(&format!(
+                r#"{{"Schedule": {{"windows": [{{"day_of_week": {}, "open_time": {}, "close_time": {}}}]}}}}"#,
+                day, open, close
+            ))
+        };
+        assert!(parse(FRIDAY as u32, 1800, 200).is_ok());
+        assert!(parse(0, 900, 1700).is_err());
+        assert!(parse(8, 900, 1700).is_err());
+        assert!(parse(MONDAY as u32, 2400, 1700).is_err());
+        assert!(parse(MONDAY as u32, 900, 1775).is_err());
+    }
+}
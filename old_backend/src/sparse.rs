@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::helpers::insert_top_k;
+
+/// Non-zero dimensions of a sparse embedding (SPLADE style term weights).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct SparseVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+impl SparseVector {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.indices.len() != self.values.len() {
+            return Err(format!(
+                "Sparse vector has {} indices but {} values",
+                self.indices.len(),
+                self.values.len()
+            ));
+        }
+        if self.values.iter().any(|x| !x.is_finite()) {
+            return Err("Sparse vector values must be finite".to_string());
+        }
+        let mut seen = self.indices.clone();
+        seen.sort();
+        if seen.windows(2).any(|x| x[0] == x[1]) {
+            return Err("Sparse vector has duplicate indices".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Inverted index from sparse dimension to the records with a weight there,
+/// so a query only touches the postings of its own non-zero dimensions.
+#[derive(Default)]
+pub(crate) struct SparseIndex {
+    postings: HashMap<u32, HashMap<usize, f32>>,
+    /// Dimensions each record was added with, so removal knows which postings to touch.
+    doc_dims: HashMap<usize, Vec<u32>>,
+}
+
+impl SparseIndex {
+    /// Replaces whatever `id` had before.
+    pub fn add(&mut self, id: usize, vector: &SparseVector) {
+        self.remove(id);
+        for (dim, value) in vector.indices.iter().zip(&vector.values) {
+            if *value != 0.0 {
+                self.postings.entry(*dim).or_default().insert(id, *value);
+            }
+        }
+        self.doc_dims.insert(id, vector.indices.clone());
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        for dim in self.doc_dims.remove(&id).unwrap_or_default() {
+            if let Some(ids) = self.postings.get_mut(&dim) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.postings.remove(&dim);
+                }
+            }
+        }
+    }
+
+    /// Best `k` records by dot product with `query` among those `keep`
+    /// accepts, highest first. Records sharing no dimension never score.
+    pub fn search(&self, query: &SparseVector, k: usize, keep: impl Fn(usize) -> bool) -> Vec<(usize, f32)> {
+        let mut index_vec = vec![];
+        let mut dist_vec = vec![];
+        for (id, score) in self.scores(query) {
+            if keep(id) {
+                insert_top_k(&mut index_vec, &mut dist_vec, id, score, k);
+            }
+        }
+        index_vec.into_iter().zip(dist_vec).collect()
+    }
+
+    /// Dot product with `query` for every record sharing a dimension with it.
+    pub fn scores(&self, query: &SparseVector) -> HashMap<usize, f32> {
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        for (dim, weight) in query.indices.iter().zip(&query.values) {
+            for (id, value) in self.postings.get(dim).into_iter().flatten() {
+                *scores.entry(*id).or_default() += weight * value;
+            }
+        }
+        scores
+    }
+}
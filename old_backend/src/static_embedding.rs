@@ -0,0 +1,198 @@
+use std::fs;
+use serde::{Deserialize, Serialize};
+use tokenizers::Tokenizer;
+use crate::embedder::{file_id, Embedder};
+use crate::weights::Weights;
+
+/// Name `get_embeddings_only.py` exports the Llama input embeddings under.
+const DEFAULT_TENSOR_NAME: &str = "tok_embeddings.weight";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StaticEmbeddingConfig {
+    /// safetensors file holding a [vocab, dims] token-embedding matrix.
+    pub weights_path: String,
+    /// HuggingFace `tokenizer.json` (BPE or converted SentencePiece).
+    pub tokenizer_path: String,
+    pub tensor_name: Option<String>,
+    /// JSON array of per-token-id weights; tokens past its end weigh 1.
+    pub idf_path: Option<String>,
+}
+
+/// Embeds text as the normalized, optionally IDF-weighted mean of its token
+/// embeddings. Plain CPU arithmetic, no model runtime needed.
+pub struct StaticEmbedding {
+    tokenizer: Tokenizer,
+    /// Row-major [vocab, dims].
+    matrix: Vec<f32>,
+    vocab: usize,
+    dims: usize,
+    idf: Option<Vec<f32>>,
+    model_id: String,
+}
+
+impl StaticEmbedding {
+    pub fn load(config: &StaticEmbeddingConfig) -> Result<Self, String> {
+        let tensor_name = config.tensor_name.as_deref().unwrap_or(DEFAULT_TENSOR_NAME);
+        let (matrix, shape) = Weights::load(&config.weights_path)?.tensor(tensor_name)?;
+        let [vocab, dims] = shape[..] else {
+            return Err(format!("Tensor {} has shape {:?}, expected [vocab, dims]", tensor_name, shape));
+        };
+        let tokenizer = Tokenizer::from_file(&config.tokenizer_path)
+            .map_err(|e| format!("Can't load tokenizer {}: {}", config.tokenizer_path, e))?;
+        let idf = match &config.idf_path {
+            Some(path) => {
+                let text = fs::read_to_string(path).map_err(|e| format!("Can't read IDF weights {}: {}", path, e))?;
+                Some(serde_json::from_str(&text).map_err(|e| format!("Invalid IDF weights {}: {}", path, e))?)
+            }
+            None => None,
+        };
+        let model_id = format!(
+            "static:{}:{}:{}:{}",
+            file_id(&config.weights_path),
+            tensor_name,
+            file_id(&config.tokenizer_path),
+            config.idf_path.as_deref().map(file_id).unwrap_or_default()
+        );
+        Ok(Self { tokenizer, matrix, vocab, dims, idf, model_id })
+    }
+
+    /// Errs rather than return a zero vector, which would match nothing
+    /// under cosine and poison every mean it's part of.
+    fn pool(&self, ids: &[u32]) -> Result<Vec<f32>, String> {
+        let mut sum = vec![0.0; self.dims];
+        for &id in ids {
+            let id = id as usize;
+            // Tokenizers can carry added tokens the matrix was never trained with.
+            if id >= self.vocab {
+                continue;
+            }
+            let weight = self.idf.as_ref().and_then(|idf| idf.get(id)).copied().unwrap_or(1.0);
+            let row = &self.matrix[id * self.dims..(id + 1) * self.dims];
+            for (total, x) in sum.iter_mut().zip(row) {
+                *total += weight * x;
+            }
+        }
+        // Dividing by the weight total first wouldn't change the direction.
+        let norm = sum.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm == 0.0 {
+            return Err("Text has no in-vocabulary tokens to embed".to_string());
+        }
+        sum.iter_mut().for_each(|x| *x /= norm);
+        Ok(sum)
+    }
+}
+
+impl Embedder for StaticEmbedding {
+    fn dims(&self) -> usize {
+        self.dims
+    }
+
+    fn model_id(&self) -> String {
+        self.model_id.clone()
+    }
+
+    fn get_embedding(&self, text: &str) -> Result<Vec<f32>, String> {
+        let encoding = self.tokenizer.encode(text, false).map_err(|e| format!("Can't tokenize text: {}", e))?;
+        self.pool(encoding.get_ids())
+    }
+
+    fn get_embeddings(&self, texts: &[String]) -> Vec<Result<Vec<f32>, String>> {
+        let inputs: Vec<&str> = texts.iter().map(|x| x.as_str()).collect();
+        match self.tokenizer.encode_batch(inputs, false) {
+            Ok(encodings) => encodings.iter().map(|x| self.pool(x.get_ids())).collect(),
+            // One bad text fails the whole batch; go one by one to find it.
+            Err(_) => texts.iter().map(|text| self.get_embedding(text)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use safetensors::tensor::TensorView;
+    use safetensors::Dtype;
+    use std::path::{Path, PathBuf};
+
+    /// w0..w3 get rows of a [4, 2] matrix; [UNK] is id 4, past the matrix,
+    /// like an added token the embeddings were never trained with.
+    fn write_model(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("static-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let matrix: Vec<u8> = [1.0f32, 0.0, 0.0, 1.0, 1.0, 1.0, 3.0, 4.0]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let view = TensorView::new(Dtype::F32, vec![4, 2], &matrix).unwrap();
+        let bytes = safetensors::serialize([(DEFAULT_TENSOR_NAME, view)], &None).unwrap();
+        fs::write(dir.join("model.safetensors"), bytes).unwrap();
+        let tokenizer = serde_json::json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": { "type": "WhitespaceSplit" },
+            "post_processor": null,
+            "decoder": null,
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "w0": 0, "w1": 1, "w2": 2, "w3": 3, "[UNK]": 4 },
+                "unk_token": "[UNK]"
+            }
+        });
+        fs::write(dir.join("tokenizer.json"), tokenizer.to_string()).unwrap();
+        // w0 and w1 get weights; w2 and w3 are past the end and weigh 1.
+        fs::write(dir.join("idf.json"), "[1.0, 3.0]").unwrap();
+        dir
+    }
+
+    fn load(dir: &Path, idf: bool) -> StaticEmbedding {
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        StaticEmbedding::load(&StaticEmbeddingConfig {
+            weights_path: path("model.safetensors"),
+            tokenizer_path: path("tokenizer.json"),
+            tensor_name: None,
+            idf_path: idf.then(|| path("idf.json")),
+        })
+        .unwrap()
+    }
+
+    fn assert_close(got: &[f32], expected: &[f32]) {
+        assert_eq!(got.len(), expected.len());
+        for (got, expected) in got.iter().zip(expected) {
+            assert!((got - expected).abs() < 1e-6, "{:?} != {:?}", got, expected);
+        }
+    }
+
+    #[test]
+    fn empty_and_out_of_vocabulary_text_fail() {
+        let dir = write_model("oov");
+        let model = load(&dir, false);
+        assert!(model.get_embedding("").is_err());
+        assert!(model.get_embedding("nothing known").is_err());
+        // Unknown tokens are skipped, the known ones still count.
+        assert_close(&model.get_embedding("w3 nothing").unwrap(), &[0.6, 0.8]);
+        let texts = vec!["w0".to_string(), "nothing".to_string(), "".to_string()];
+        let results = model.get_embeddings(&texts);
+        assert_close(results[0].as_ref().unwrap(), &[1.0, 0.0]);
+        assert!(results[1].is_err());
+        assert!(results[2].is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn idf_weights_the_mean() {
+        let dir = write_model("idf");
+        let plain = load(&dir, false);
+        let weighted = load(&dir, true);
+        let half = 0.5f32.sqrt();
+        assert_close(&plain.get_embedding("w0 w1").unwrap(), &[half, half]);
+        // w1 weighs 3: (1, 0) + 3 * (0, 1), normalized.
+        let tenth = 0.1f32.sqrt();
+        assert_close(&weighted.get_embedding("w0 w1").unwrap(), &[tenth, 3.0 * tenth]);
+        // Tokens past the end of the IDF table weigh 1.
+        assert_close(&weighted.get_embedding("w0 w2").unwrap(), &plain.get_embedding("w0 w2").unwrap());
+        assert_ne!(plain.model_id(), weighted.model_id());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
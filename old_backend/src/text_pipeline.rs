@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/// Cleanup run on text before it reaches the embedder. Records still store
+/// the text exactly as it was inserted. Steps run in field order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct TextPipeline {
+    /// Drops tags (and script/style bodies) and decodes entities.
+    #[serde(default)]
+    pub strip_html: bool,
+    #[serde(default)]
+    pub nfkc: bool,
+    #[serde(default)]
+    pub collapse_whitespace: bool,
+    #[serde(default)]
+    pub lowercase: bool,
+    /// Truncate to this many chars.
+    pub max_chars: Option<usize>,
+}
+
+impl TextPipeline {
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = if self.strip_html { strip_html(text) } else { text.to_string() };
+        if self.nfkc {
+            text = text.nfkc().collect();
+        }
+        if self.collapse_whitespace {
+            text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        }
+        if self.lowercase {
+            text = text.to_lowercase();
+        }
+        if let Some((end, _)) = self.max_chars.and_then(|max| text.char_indices().nth(max)) {
+            text.truncate(end);
+        }
+        text
+    }
+}
+
+/// Replaces every tag with a space so words on either side stay apart.
+fn strip_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        let tag = &rest[open..];
+        let close = match tag.find('>') {
+            Some(x) => x,
+            None => {
+                rest = tag;
+                break;
+            }
+        };
+        let inner = &tag[1..close];
+        let name = inner
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        rest = &tag[close + 1..];
+        if name == "script" || name == "style" {
+            // Skip the body; ASCII lowercasing keeps byte offsets valid.
+            let end_tag = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&end_tag) {
+                Some(end) => rest[end..].find('>').map_or("", |x| &rest[end + x + 1..]),
+                None => "",
+            };
+        }
+        out.push(' ');
+    }
+    out.push_str(rest);
+    html_escape::decode_html_entities(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tidy(html: &str) -> String {
+        TextPipeline { strip_html: true, collapse_whitespace: true, ..Default::default() }.apply(html)
+    }
+
+    #[test]
+    fn decodes_entities_after_dropping_tags() {
+        assert_eq!(tidy("Fish &amp; chips &#8211; &quot;fresh&quot; &#x27;daily&#x27;"), "Fish & chips – \"fresh\" 'daily'");
+        // Escaped markup is text, so it survives as literal brackets.
+        assert_eq!(tidy("Use &lt;b&gt; for bold"), "Use <b> for bold");
+    }
+
+    #[test]
+    fn strips_nested_tags_and_keeps_words_apart() {
+        assert_eq!(
+            tidy("<div class=\"menu\"><p>Open <b>late<i>!</i></b></p><p>Tues</p></div>"),
+            "Open late ! Tues"
+        );
+        assert_eq!(tidy("line<br/>break"), "line break");
+    }
+
+    #[test]
+    fn drops_script_and_style_bodies() {
+        assert_eq!(tidy("a<script type=\"x\">var b = '<p>';</script>c"), "a c");
+        assert_eq!(tidy("a<STYLE>p { color: red }</Style >c"), "a c");
+        // An unterminated script hides the rest of the document.
+        assert_eq!(tidy("a<script>never closed"), "a");
+    }
+
+    #[test]
+    fn leaves_an_unclosed_tag_as_text() {
+        assert_eq!(tidy("1 < 2"), "1 < 2");
+    }
+
+    #[test]
+    fn normalizes_in_field_order() {
+        let pipeline = TextPipeline {
+            strip_html: true,
+            nfkc: true,
+            collapse_whitespace: true,
+            lowercase: true,
+            max_chars: Some(9),
+        };
+        // NFKC folds the ligature and full-width letters before lowercasing.
+        assert_eq!(pipeline.apply("<p>ﬁne  \n ＣＡＦÉ</p> menu"), "fine café");
+        assert_eq!(TextPipeline::default().apply("  Keep <b>AS</b> is "), "  Keep <b>AS</b> is ");
+    }
+
+    #[test]
+    fn truncates_on_char_boundaries() {
+        let pipeline = TextPipeline { max_chars: Some(3), ..Default::default() };
+        assert_eq!(pipeline.apply("héllo"), "hél");
+        assert_eq!(pipeline.apply("hé"), "hé");
+    }
+}
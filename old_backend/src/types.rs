@@ -1,6 +1,17 @@
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use crate::node_interface::NodeInterface;
+use crate::payload::{Filter, Payload, PayloadValue};
+use crate::sparse::SparseVector;
+use crate::chunking::{ChunkInfo, ChunkOptions};
+use crate::embedder::EmbedderSpec;
+use crate::embedding_cache::CacheStats;
+use crate::collection::CollectionInfo;
+use crate::payload_index::PayloadIndexType;
+use crate::query_planner::QueryPlan;
+use crate::vector_db::Stored;
 use tch::Tensor;
+use crate::helpers::cosine_similarity_rust_float;
 
 pub(crate) struct Node<T> {
     pub data: Vec<T>,
@@ -9,86 +20,416 @@ pub(crate) struct Node<T> {
 
 pub(crate) enum TreeNode<T> {
     LeafNode(Node<T>),
-    Null,
-    OverflowNode(Box<TreeNode<T>>, Tensor, Box<TreeNode<T>>),
 }
 
 pub(crate) enum ChildType<T> {
     Data(T),
-    Node(TreeNode<T>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) enum Response {
     Success,
     Error(String),
-    Data(Vec<String>),
-    Indexes(Vec<usize>)
+    /// A single insert; `duplicate` when the dedupe policy caught it, whether
+    /// it then kept the existing record or stored a flagged copy.
+    Inserted { id: usize, duplicate: bool },
+    Indexes(Vec<usize>),
+    Plan(QueryPlan),
+    Hits(Vec<SearchHit<String>>),
+    InsertResults(Vec<InsertResult>),
+    IngestReport(IngestReport),
+    /// One Hits or Error per query, in request order.
+    BatchHits(Vec<Response>),
+    Groups(Vec<HitGroup<String>>),
+    CacheStats(CacheStats),
+    Collection(CollectionInfo),
+}
+
+/// Summary of an NDJSON ingest; `line` numbers are 1-based.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct IngestReport {
+    pub lines: usize,
+    pub inserted: usize,
+    /// Lines the dedupe policy caught; flagged ones were still stored.
+    pub duplicates: usize,
+    pub errors: Vec<LineError>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub (crate) struct InsertRequest {
-    pub(crate) entry: String,
+pub(crate) struct LineError {
+    pub line: usize,
+    pub error: String,
 }
 
+/// Outcome of one entry in a bulk insert, `index` being its position in the request.
 #[derive(Debug, Serialize, Deserialize)]
-pub (crate) struct GetRequest {
-    query: String,
+pub(crate) struct InsertResult {
+    pub index: usize,
+    pub id: Option<usize>,
+    /// Same meaning as in `Response::Inserted`.
+    pub duplicate: bool,
+    pub error: Option<String>,
 }
 
-pub (crate) enum Request {
-    Insert { id: String, text: String },
-    Get { id: String },
-    Shutdown,
+impl InsertResult {
+    pub fn from_result(index: usize, result: Result<Stored, String>) -> Self {
+        match result {
+            Ok(stored) => Self { index, id: Some(stored.id()), duplicate: stored.is_duplicate(), error: None },
+            Err(e) => Self { index, id: None, duplicate: false, error: Some(e) },
+        }
+    }
 }
 
-impl<T> NodeInterface<T> for Node<T> {
-    fn new() -> Self {
-        Self {
-            data: Vec::new(),
-            indexes: Vec::new(),
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct HitGroup<T> {
+    pub group: PayloadValue,
+    pub hits: Vec<SearchHit<T>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) enum DistanceMetric {
+    #[default]
+    Cosine,
+    Dot,
+    Euclidean,
+}
+
+impl DistanceMetric {
+    pub fn score(&self, l: &Tensor, r: &Tensor) -> f32 {
+        match self {
+            DistanceMetric::Cosine => cosine_similarity_rust_float(l, r),
+            DistanceMetric::Dot => l.dot(r).double_value(&[]) as f32,
+            DistanceMetric::Euclidean => (l - r).norm().double_value(&[]) as f32,
         }
     }
 
-    fn reverse_data(&mut self) {
-        self.data.reverse();
-        self.indexes.reverse();
+    /// Maps a score onto a scale where higher is always better, for the
+    /// top-k helpers. Applying it twice gives the score back.
+    pub fn rank_key(&self, score: f32) -> f32 {
+        if self.higher_is_better() {
+            score
+        } else {
+            -score
+        }
     }
 
-    fn pop_last_data_and_index(&mut self) -> Option<(Tensor, ChildType<T>)> {
-        match (self.indexes.pop(), self.data.pop()) {
-            (Some(index), Some(data)) => Some((index, ChildType::Data(data))),
-            (_, _) => None,
+    /// Whether a larger score means a closer match.
+    pub fn higher_is_better(&self) -> bool {
+        match self {
+            DistanceMetric::Cosine | DistanceMetric::Dot => true,
+            DistanceMetric::Euclidean => false,
         }
     }
 
-    fn push_back(&mut self, index: Tensor, datum: ChildType<T>) {
-        match datum {
-            ChildType::Data(x) => self.data.push(x),
-            _ => panic!("Tried to insert non data type into data"),
+    pub fn within(&self, score: f32, threshold: f32) -> bool {
+        if self.higher_is_better() {
+            score >= threshold
+        } else {
+            score <= threshold
         }
-        self.indexes.push(index);
     }
 
-    fn get_loc(&self, index: &Tensor) -> usize {
-        unimplemented!()
+    /// Best match first.
+    pub fn compare(&self, a: f32, b: f32) -> std::cmp::Ordering {
+        if self.higher_is_better() {
+            b.total_cmp(&a)
+        } else {
+            a.total_cmp(&b)
+        }
     }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SearchHit<T> {
+    pub id: usize,
+    pub score: f32,
+    pub metric: DistanceMetric,
+    pub data: T,
+    pub payload: Payload,
+    pub vector: Option<Vec<f32>>,
+    /// Set on hits that are a chunk of a longer document.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<ChunkInfo>,
+}
 
-    fn get_midpoint_idx(&self) -> usize {
-        return self.data.len().div_ceil(2);
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct InsertRequest {
+    pub(crate) entry: String,
+    #[serde(default)]
+    pub(crate) payload: Payload,
+    /// Store the entry as overlapping chunks instead of one record.
+    pub(crate) chunk: Option<ChunkOptions>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct BatchInsertRequest {
+    pub(crate) entries: Vec<InsertRequest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct InsertVectorRequest {
+    pub(crate) entry: String,
+    pub(crate) vector: Vec<f32>,
+    #[serde(default)]
+    pub(crate) payload: Payload,
+    pub(crate) sparse: Option<SparseVector>,
+    /// Vectors for named vector spaces, by space name.
+    #[serde(default)]
+    pub(crate) named: HashMap<String, Vec<f32>>,
+    /// `model_id` of the model that produced `vector`; optional, checked against the collection's when given.
+    pub(crate) model: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct CreateVectorSpaceRequest {
+    pub(crate) name: String,
+    pub(crate) dims: usize,
+    #[serde(default)]
+    pub(crate) metric: DistanceMetric,
+    /// Llamafile model to embed text into this space; without one (or
+    /// `embedder`) the space only takes precomputed vectors.
+    pub(crate) model_path: Option<String>,
+    pub(crate) embedder: Option<EmbedderSpec>,
+}
+
+/// Sets a record's vector in a named space, from `vector` or by embedding `text`.
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct SetNamedVectorRequest {
+    pub(crate) id: usize,
+    pub(crate) name: String,
+    pub(crate) vector: Option<Vec<f32>>,
+    pub(crate) text: Option<String>,
+}
+
+/// A record stored as a bag of vectors. Without `vectors` the entry is split
+/// into sentences and each one is embedded.
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct InsertMultiVectorRequest {
+    pub(crate) entry: String,
+    pub(crate) vectors: Option<Vec<Vec<f32>>>,
+    #[serde(default)]
+    pub(crate) payload: Payload,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct UpdateSparseRequest {
+    pub(crate) id: usize,
+    pub(crate) sparse: SparseVector,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct UpdatePayloadRequest {
+    pub(crate) id: usize,
+    pub(crate) payload: Payload,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct DeleteRequest {
+    pub(crate) id: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct CreateIndexRequest {
+    pub(crate) key: String,
+    pub(crate) index_type: PayloadIndexType,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct FilterRequest {
+    pub(crate) filter: Option<Filter>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct GetRequest {
+    query: String,
+}
+
+/// Maximal Marginal Relevance: fetch `fetch_k` candidates, then pick `k`
+/// of them trading relevance against similarity to what's already picked.
+/// `lambda` = 1.0 is plain relevance order, 0.0 is maximum diversity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub (crate) struct MmrOptions {
+    pub(crate) lambda: f32,
+    pub(crate) fetch_k: Option<usize>,
+}
+
+impl MmrOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.lambda) {
+            return Err(format!("MMR lambda must be between 0 and 1, got {}", self.lambda));
+        }
+        if self.fetch_k == Some(0) {
+            return Err("MMR fetch_k must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How hybrid search merges the vector and BM25 rankings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub (crate) enum Fusion {
+    /// Reciprocal rank fusion: sum of 1 / (k + rank) over both lists.
+    Rrf { k: f32 },
+    /// Min-max normalizes each list, then blends them; 1.0 is vector only.
+    Weighted { dense_weight: f32 },
+}
+
+/// Sparse dot-product search. With a dense `query` or `vector` as well, both
+/// sides are searched and fused (RRF with k = 60 unless `fusion` says otherwise).
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct SparseSearchRequest {
+    pub(crate) sparse: SparseVector,
+    pub(crate) query: Option<String>,
+    pub(crate) vector: Option<Vec<f32>>,
+    pub(crate) k: Option<usize>,
+    pub(crate) filter: Option<Filter>,
+    pub(crate) fusion: Option<Fusion>,
+    pub(crate) fetch_k: Option<usize>,
+    #[serde(default)]
+    pub(crate) with_vector: bool,
+}
+
+/// Late-interaction search over multi-vector records. Text queries are split
+/// into sentences the same way inserts are.
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct MultiVectorSearchRequest {
+    pub(crate) query: Option<String>,
+    pub(crate) vectors: Option<Vec<Vec<f32>>>,
+    pub(crate) k: Option<usize>,
+    pub(crate) filter: Option<Filter>,
+    /// Nearest stored vectors pulled per query vector as candidates; defaults to a multiple of k.
+    pub(crate) per_vector: Option<usize>,
+    #[serde(default)]
+    pub(crate) with_vector: bool,
+}
+
+/// Runs BM25 over the stored text next to the vector search and fuses the
+/// two. Hit scores are then the fused score, not a similarity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub (crate) struct HybridOptions {
+    pub(crate) fusion: Fusion,
+    /// Candidates taken from each side before fusing; defaults to a multiple of k.
+    pub(crate) fetch_k: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct SearchRequest {
+    pub(crate) query: String,
+    pub(crate) k: Option<usize>,
+    pub(crate) filter: Option<Filter>,
+    #[serde(default)]
+    pub(crate) with_vector: bool,
+    pub(crate) mmr: Option<MmrOptions>,
+    pub(crate) hybrid: Option<HybridOptions>,
+    /// Named vector space to search instead of the default one.
+    pub(crate) using: Option<String>,
+    /// Keep only the best chunk of each chunked document.
+    #[serde(default)]
+    pub(crate) collapse_chunks: bool,
+}
+
+/// One query in a batch search: text to embed or a precomputed vector, with
+/// optional overrides for the batch-wide `k` and filter.
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct BatchQuery {
+    pub(crate) query: Option<String>,
+    pub(crate) vector: Option<Vec<f32>>,
+    pub(crate) k: Option<usize>,
+    pub(crate) filter: Option<Filter>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct BatchSearchRequest {
+    pub(crate) queries: Vec<BatchQuery>,
+    pub(crate) k: Option<usize>,
+    pub(crate) filter: Option<Filter>,
+    #[serde(default)]
+    pub(crate) with_vector: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct RecommendRequest {
+    pub(crate) positive: Vec<usize>,
+    #[serde(default)]
+    pub(crate) negative: Vec<usize>,
+    pub(crate) k: Option<usize>,
+    pub(crate) filter: Option<Filter>,
+    #[serde(default)]
+    pub(crate) with_vector: bool,
+}
+
+/// Every record scoring at least `threshold` (at most, for distance metrics)
+/// against the query text or vector.
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct RangeSearchRequest {
+    pub(crate) query: Option<String>,
+    pub(crate) vector: Option<Vec<f32>>,
+    pub(crate) threshold: f32,
+    pub(crate) limit: Option<usize>,
+    pub(crate) filter: Option<Filter>,
+    #[serde(default)]
+    pub(crate) with_vector: bool,
+    /// Send hits back as NDJSON chunks in page order instead of one sorted list.
+    #[serde(default)]
+    pub(crate) stream: bool,
+    /// Measure against this named vector space instead of the default one.
+    pub(crate) using: Option<String>,
+    /// Measure by sparse dot product with this vector instead; `query` and
+    /// `vector` must be left out.
+    pub(crate) sparse: Option<SparseVector>,
+    /// Measure by multi-vector MaxSim, with the bag from `vectors` or from
+    /// `query` split into sentences.
+    #[serde(default)]
+    pub(crate) multi_vector: bool,
+    pub(crate) vectors: Option<Vec<Vec<f32>>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct GroupSearchRequest {
+    pub(crate) query: Option<String>,
+    pub(crate) vector: Option<Vec<f32>>,
+    /// Payload field to group on, ie "business_id".
+    pub(crate) group_by: String,
+    pub(crate) group_limit: usize,
+    pub(crate) group_size: usize,
+    pub(crate) filter: Option<Filter>,
+    #[serde(default)]
+    pub(crate) with_vector: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct VectorSearchRequest {
+    pub(crate) vector: Vec<f32>,
+    pub(crate) k: Option<usize>,
+    pub(crate) filter: Option<Filter>,
+    #[serde(default)]
+    pub(crate) with_vector: bool,
+    pub(crate) mmr: Option<MmrOptions>,
+    pub(crate) using: Option<String>,
+}
+
+pub (crate) enum Request {
+    Insert { id: String, text: String },
+    Get { id: String },
+    Shutdown,
+}
+
+impl<T> NodeInterface<T> for Node<T> {
+    fn push_back(&mut self, index: Tensor, datum: ChildType<T>) {
+        let ChildType::Data(x) = datum;
+        self.data.push(x);
+        self.indexes.push(index);
     }
 
     fn get_index_len(&self) -> usize {
-        return self.indexes.len();
+        self.indexes.len()
     }
 
     fn create_new_with_data(index: Tensor, data: ChildType<T>) -> Self {
-        match data {
-            ChildType::Data(x) => Self {
-                data: vec![x],
-                indexes: vec![index],
-            },
-            _ => panic!("Tried to create node with non-data type"),
+        let ChildType::Data(x) = data;
+        Self {
+            data: vec![x],
+            indexes: vec![index],
         }
     }
 }
@@ -1,134 +1,1227 @@
-use std::mem::swap;
-use crate::helpers::{binary_search, binary_search_floats, compare, cosine_similarity_rust_float};
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use crate::bm25::{fuse, Bm25Index};
+use crate::chunking::{chunk_spans, ChunkInfo, ChunkOptions};
+use crate::dedupe::{content_hash, DedupePolicy, DuplicateAction, DUPLICATE_OF_KEY};
+use crate::helpers::{cosine_similarity_rust_float, insert_top_k, sentence_spans, tensor_to_vec, vec_to_tensor};
+use crate::collection::{CollectionConfig, CollectionInfo, ReembedOutcome, ReembedProgress};
+use crate::embedder::Embedder;
+use crate::embedding_cache::{CacheStats, EmbeddingCache};
 use crate::llama_embedding::LlamafileEmbedding;
+use crate::multi_vector::MultiVectorIndex;
 use crate::node_interface::NodeInterface;
+use crate::payload::{Filter, Payload, PayloadValue};
+use crate::payload_index::{PayloadIndex, PayloadIndexType};
+use crate::query_planner::{plan_search, QueryPlan, SearchStrategy};
+use crate::reembed::{ReembedJob, ReembedOutput};
+use crate::sparse::{SparseIndex, SparseVector};
+use crate::instructions::{EmbedRole, InstructionTemplates};
+use crate::text_pipeline::TextPipeline;
 use crate::types::*;
-use crate::vector_db::TreeNode::{LeafNode, Null};
+use crate::vector_space::{embed_texts, VectorSpace};
+use crate::vector_db::TreeNode::LeafNode;
 use tch::{Device, Tensor};
 
 const ELEMENTS_PER_PAGE: usize = 10;
+/// Texts handed to the embedder per `get_embeddings` call during bulk inserts.
+const EMBED_BATCH_SIZE: usize = 64;
+/// Default MMR candidate pool, as a multiple of k.
+const MMR_FETCH_FACTOR: usize = 4;
+/// Default per-side candidate pool for hybrid search, as a multiple of k.
+const HYBRID_FETCH_FACTOR: usize = 4;
+/// Embeddings kept in memory by the default cache.
+const EMBEDDING_CACHE_SIZE: usize = 10_000;
+/// Default multi-vector candidates per query vector, as a multiple of k.
+const MULTI_VECTOR_FETCH_FACTOR: usize = 2;
+
+/// Where an insert ended up once the dedupe policy had its say.
+pub(crate) enum Stored {
+    New(usize),
+    /// A duplicate stored anyway, with `duplicate_of` in its payload.
+    Flagged(usize),
+    /// A duplicate that was skipped or merged into this existing record.
+    Existing(usize),
+}
+
+impl Stored {
+    pub fn id(&self) -> usize {
+        match self {
+            Stored::New(id) | Stored::Flagged(id) | Stored::Existing(id) => *id,
+        }
+    }
+
+    /// The id of the record this insert created, if it created one.
+    pub fn created(&self) -> Option<usize> {
+        match self {
+            Stored::New(id) | Stored::Flagged(id) => Some(*id),
+            Stored::Existing(_) => None,
+        }
+    }
+
+    pub fn is_duplicate(&self) -> bool {
+        !matches!(self, Stored::New(_))
+    }
+}
+
+/// What a range search measures each record with.
+enum RangeScorer<'a> {
+    Dense(Tensor),
+    Space(&'a VectorSpace, Tensor),
+    /// Precomputed dot products; records sharing no dimension never score.
+    Sparse(HashMap<usize, f32>),
+    MultiVector(Vec<Tensor>),
+}
+
+impl RangeScorer<'_> {
+    /// Sparse and MaxSim scores are unbounded and higher is better, like a dot product.
+    fn metric(&self, default: DistanceMetric) -> DistanceMetric {
+        match self {
+            RangeScorer::Dense(_) => default,
+            RangeScorer::Space(space, _) => space.config.metric,
+            RangeScorer::Sparse(_) | RangeScorer::MultiVector(_) => DistanceMetric::Dot,
+        }
+    }
+}
 
 pub(crate) struct VectorDB<T: Clone> {
     data: Vec<TreeNode<T>>,
-    indexes: Vec<Tensor>,
-    embedding_item: LlamafileEmbedding,
-    zero: Tensor,
+    /// None once a record has been deleted; ids are never reused.
+    payloads: Vec<Option<Payload>>,
+    payload_indexes: HashMap<String, PayloadIndex>,
+    /// Keyword index over the text records were embedded from.
+    text_index: Bm25Index,
+    /// Optional sparse vector per record, sharing ids with the dense pages.
+    sparse_index: SparseIndex,
+    /// Per-sentence (or per-token) vectors for records inserted as a bag.
+    multi_vectors: MultiVectorIndex,
+    /// Extra named vector fields, each with its own dims, metric and embedder.
+    vector_spaces: HashMap<String, VectorSpace>,
+    /// Parent document and offsets for records that are chunks.
+    chunks: HashMap<usize, ChunkInfo>,
+    /// Applied to every text on its way to an embedder, inserts and queries alike.
+    text_pipeline: TextPipeline,
+    instructions: InstructionTemplates,
+    /// Shared by every embedder in the collection; None when disabled.
+    embedding_cache: Option<EmbeddingCache>,
+    dedupe: DedupePolicy,
+    /// Hash of each inserted text (after the pipeline) to the first live
+    /// record holding it, and the reverse for cleanup on delete.
+    content_hashes: HashMap<[u8; 32], usize>,
+    record_hashes: HashMap<usize, [u8; 32]>,
+    /// Text each record was embedded from, kept so the collection can be
+    /// re-embedded with another model. Records inserted as vectors have none.
+    source_texts: HashMap<usize, String>,
+    /// Progress counter shared with the running re-embedding job, and the
+    /// size of its snapshot.
+    reembedding: Option<(Arc<AtomicUsize>, usize)>,
+    last_reembed: Option<ReembedOutcome>,
+    embedding_item: Box<dyn Embedder + Send>,
+    config: CollectionConfig,
 }
 
 impl<T: Clone> VectorDB<T> {
-    pub fn new(model_path: &str, dims: usize) -> Self {
-        let zero = Tensor::zeros(&[dims as i64], (tch::Kind::Float, Device::Cuda(0)));
-        let embedding_model = LlamafileEmbedding::new(model_path);
-        Self {
+    /// A collection over a llamafile model, as wide as the model's vectors.
+    pub fn new(model_path: &str) -> Result<Self, String> {
+        let embedding_model = LlamafileEmbedding::new(model_path)?;
+        Self::with_embedder(Box::new(embedding_model), None)
+    }
+
+    /// Builds a collection around `embedder`. When `dims` is None the
+    /// collection takes its dimension from the embedder.
+    pub fn with_embedder(embedder: Box<dyn Embedder + Send>, dims: Option<usize>) -> Result<Self, String> {
+        let dims = dims.unwrap_or(embedder.dims());
+        if embedder.dims() != dims {
+            return Err(format!(
+                "Collection is configured for {} dimensions but the embedder produces {}",
+                dims,
+                embedder.dims()
+            ));
+        }
+        let model_id = Some(embedder.model_id());
+        Ok(Self {
             data: vec![],
-            embedding_item: embedding_model,
-            zero: zero,
-            indexes: vec![], // compare: Box::ne,
+            embedding_item: embedder,
+            payloads: vec![],
+            payload_indexes: HashMap::new(),
+            text_index: Bm25Index::default(),
+            sparse_index: SparseIndex::default(),
+            multi_vectors: MultiVectorIndex::default(),
+            vector_spaces: HashMap::new(),
+            chunks: HashMap::new(),
+            text_pipeline: TextPipeline::default(),
+            instructions: InstructionTemplates::default(),
+            embedding_cache: Some(EmbeddingCache::new(EMBEDDING_CACHE_SIZE, None)?),
+            dedupe: DedupePolicy::default(),
+            content_hashes: HashMap::new(),
+            record_hashes: HashMap::new(),
+            source_texts: HashMap::new(),
+            reembedding: None,
+            last_reembed: None,
+            config: CollectionConfig {
+                dims,
+                metric: DistanceMetric::Cosine,
+                model_id,
+            },
+        })
+    }
+
+    /// Appends to the last page so a record's id (page * ELEMENTS_PER_PAGE + slot)
+    /// stays stable and lines up with its entry in `payloads`.
+    pub fn insert(&mut self, new_data: T, index_string: String, payload: Payload) -> Result<Stored, String> {
+        let query = self.embed(&index_string, EmbedRole::Document)?;
+        let named = self.embed_named(std::slice::from_ref(&index_string)).pop().unwrap()?;
+        Ok(self.insert_deduped(new_data, &index_string, true, query, named, payload))
+    }
+
+    /// Embeds `items` through the embedder's batch path, EMBED_BATCH_SIZE at a
+    /// time, and appends whatever succeeded. Results line up with `items`.
+    pub fn insert_batch(&mut self, items: Vec<(T, String, Payload)>) -> Vec<Result<Stored, String>> {
+        let mut results = Vec::with_capacity(items.len());
+        let mut items = items.into_iter().peekable();
+        while items.peek().is_some() {
+            let batch: Vec<(T, String, Payload)> = items.by_ref().take(EMBED_BATCH_SIZE).collect();
+            let texts: Vec<String> = batch.iter().map(|(_, text, _)| text.clone()).collect();
+            let vectors = self.embed_batch(&texts, EmbedRole::Document);
+            let named = self.embed_named(&texts);
+            for (((new_data, text, payload), vector), named) in batch.into_iter().zip(vectors).zip(named) {
+                results.push(vector.and_then(|query| Ok(self.insert_deduped(new_data, &text, true, query, named?, payload))));
+            }
+        }
+        results
+    }
+
+    /// Inserts a precomputed embedding instead of running the model, plus
+    /// an optional sparse vector for the same record. The entry text is
+    /// still keyword-indexed and deduplicated but kept out of re-embedding.
+    pub fn insert_vector(&mut self, new_data: T, request: InsertVectorRequest) -> Result<Stored, String> {
+        self.check_not_reembedding()?;
+        self.config.check_dims(&request.vector)?;
+        self.config.check_model(request.model.as_deref())?;
+        if let Some(sparse) = &request.sparse {
+            sparse.validate()?;
+        }
+        let named = request
+            .named
+            .iter()
+            .map(|(name, vector)| Ok((name.clone(), self.vector_space(name)?.tensor(vector)?)))
+            .collect::<Result<Vec<(String, Tensor)>, String>>()?;
+        let query = vec_to_tensor(&request.vector);
+        let stored = self.insert_deduped(new_data, &request.entry, false, query, named, request.payload);
+        if let (Some(id), Some(sparse)) = (stored.created(), &request.sparse) {
+            self.sparse_index.add(id, sparse);
+        }
+        Ok(stored)
+    }
+
+    /// Stores the record as a bag of vectors, one per sentence of `text`
+    /// unless `vectors` are given. Their mean becomes the record's dense
+    /// vector so ordinary searches still find it.
+    pub fn insert_multi_vector(
+        &mut self,
+        new_data: T,
+        text: String,
+        vectors: Option<Vec<Vec<f32>>>,
+        payload: Payload,
+    ) -> Result<Stored, String> {
+        self.check_not_reembedding()?;
+        let bag = self.bag_of_vectors(Some(&text), vectors.as_deref(), EmbedRole::Document)?;
+        let mean = bag.iter().skip(1).fold(bag[0].copy(), |acc, x| acc + x) / bag.len() as f64;
+        let named = self.embed_named(std::slice::from_ref(&text)).pop().unwrap()?;
+        let stored = self.insert_deduped(new_data, &text, true, mean, named, payload);
+        if let Some(id) = stored.created() {
+            self.multi_vectors.add(id, bag);
         }
+        Ok(stored)
     }
 
-    pub fn insert(&mut self, new_data: T, index_string: String) {
-        let query = self.embedding_item.get_embedding(&index_string);
-        let compare_func = compare(&self.zero);
-        if self.data.is_empty() {
-            self.data.push(LeafNode(Node {
-                data: vec![new_data],
-                indexes: vec![query],
-            }));
+    /// Splits `text` per `options` and stores every chunk as its own record
+    /// carrying `payload`. All chunks go in or none do. Returns the parent
+    /// id, which is the first chunk's record id.
+    pub fn insert_chunked(&mut self, text: &str, payload: Payload, options: &ChunkOptions) -> Result<usize, String>
+    where
+        T: From<String>,
+    {
+        let spans = chunk_spans(text, options)?;
+        if spans.is_empty() {
+            return Err("Nothing to chunk".to_string());
+        }
+        let chunks: Vec<String> = spans.iter().map(|(start, end)| text[*start..*end].to_string()).collect();
+        let vectors = self.embed_batch(&chunks, EmbedRole::Document).into_iter().collect::<Result<Vec<Tensor>, String>>()?;
+        let named = self
+            .embed_named(&chunks)
+            .into_iter()
+            .collect::<Result<Vec<Vec<(String, Tensor)>>, String>>()?;
+        let parent = self.payloads.len();
+        for (((chunk, (start, end)), vector), named) in chunks.into_iter().zip(spans).zip(vectors).zip(named) {
+            let id = self.insert_text_record(T::from(chunk.clone()), &chunk, true, vector, named, payload.clone());
+            self.chunks.insert(id, ChunkInfo { parent, start, end });
+        }
+        Ok(parent)
+    }
+
+    /// Runs the dedupe policy before appending. Chunks skip this: a repeated
+    /// paragraph inside a longer document is not a duplicate document.
+    /// `embedded` is false when the caller brought its own vector.
+    fn insert_deduped(
+        &mut self,
+        new_data: T,
+        text: &str,
+        embedded: bool,
+        query: Tensor,
+        named: Vec<(String, Tensor)>,
+        mut payload: Payload,
+    ) -> Stored {
+        let hash = content_hash(&self.text_pipeline.apply(text));
+        let mut flagged = false;
+        if let Some(existing) = self.find_duplicate(&hash, &query) {
+            match self.dedupe.action {
+                DuplicateAction::Skip => return Stored::Existing(existing),
+                DuplicateAction::MergePayload => {
+                    let mut merged = self.live_payload(existing).cloned().unwrap_or_default();
+                    merged.extend(payload);
+                    self.update_payload(existing, merged);
+                    return Stored::Existing(existing);
+                }
+                DuplicateAction::InsertAndFlag => {
+                    payload.insert(DUPLICATE_OF_KEY.to_string(), PayloadValue::Integer(existing as i64));
+                    flagged = true;
+                }
+            }
+        }
+        let id = self.insert_text_record(new_data, text, embedded, query, named, payload);
+        self.content_hashes.entry(hash).or_insert(id);
+        self.record_hashes.insert(id, hash);
+        if flagged {
+            Stored::Flagged(id)
         } else {
-            let loc = binary_search(&self.indexes, &query, &compare_func);
-            let mut item = Null;
-            swap(&mut item, &mut self.data[loc]);
-            insert_into_tree_node(item, new_data, query, compare_func);
+            Stored::New(id)
+        }
+    }
+
+    fn find_duplicate(&self, hash: &[u8; 32], query: &Tensor) -> Option<usize> {
+        if self.dedupe.exact {
+            if let Some(existing) = self.content_hashes.get(hash) {
+                return Some(*existing);
+            }
+        }
+        let threshold = self.dedupe.near_threshold?;
+        self.top_k(query, 1, None)
+            .first()
+            .filter(|(_, score)| self.config.metric.within(*score, threshold))
+            .map(|(idx, _)| *idx)
+    }
+
+    /// Applies to inserts from now on; nothing already stored is re-checked.
+    pub fn set_dedupe_policy(&mut self, policy: DedupePolicy) {
+        self.dedupe = policy;
+    }
+
+    /// Appends a record along with its text: keyword-indexes the text and
+    /// fills in the named spaces. Only text the collection `embedded` itself
+    /// is kept for re-embedding.
+    fn insert_text_record(
+        &mut self,
+        new_data: T,
+        text: &str,
+        embedded: bool,
+        query: Tensor,
+        named: Vec<(String, Tensor)>,
+        payload: Payload,
+    ) -> usize {
+        let id = self.insert_tensor(new_data, query, payload);
+        self.text_index.add(id, text);
+        if embedded {
+            self.source_texts.insert(id, text.to_string());
+        }
+        self.set_named(id, named);
+        id
+    }
+
+    /// Document vectors for every named space that has its own embedder;
+    /// results line up with `texts`.
+    fn embed_named(&self, texts: &[String]) -> Vec<Result<Vec<(String, Tensor)>, String>> {
+        let texts = &self.prepare_all(texts, EmbedRole::Document);
+        let mut results: Vec<Result<Vec<(String, Tensor)>, String>> = texts.iter().map(|_| Ok(vec![])).collect();
+        for (name, space) in self.vector_spaces.iter().filter(|(_, space)| space.has_embedder()) {
+            for (result, vector) in results.iter_mut().zip(space.embed_batch(texts, self.embedding_cache.as_ref())) {
+                *result = match (std::mem::replace(result, Ok(vec![])), vector) {
+                    (Ok(mut named), Ok(vector)) => {
+                        named.push((name.clone(), vector));
+                        Ok(named)
+                    }
+                    (Err(e), _) => Err(e),
+                    (Ok(_), Err(e)) => Err(format!("Vector space {}: {}", name, e)),
+                };
+            }
+        }
+        results
+    }
+
+    fn set_named(&mut self, id: usize, named: Vec<(String, Tensor)>) {
+        for (name, vector) in named {
+            if let Some(space) = self.vector_spaces.get_mut(&name) {
+                space.set(id, vector);
+            }
+        }
+    }
+
+    fn vector_space(&self, name: &str) -> Result<&VectorSpace, String> {
+        self.vector_spaces
+            .get(name)
+            .ok_or(format!("Unknown vector space {}", name))
+    }
+
+    /// Stored vectors and content hashes came from text cleaned up by the
+    /// current pipeline, so it can only change while the collection is empty.
+    pub fn set_text_pipeline(&mut self, pipeline: TextPipeline) -> Result<(), String> {
+        self.check_empty("text pipeline")?;
+        self.text_pipeline = pipeline;
+        Ok(())
+    }
+
+    fn check_empty(&self, setting: &str) -> Result<(), String> {
+        match self.live_count() {
+            0 => Ok(()),
+            records => Err(format!("Can't change the {} of a collection that has {} records", setting, records)),
+        }
+    }
+
+    fn live_count(&self) -> usize {
+        self.payloads.iter().filter(|x| x.is_some()).count()
+    }
+
+    /// Stored vectors were embedded with the current document template, and
+    /// re-embedding replays it, so like the pipeline it's fixed once records exist.
+    pub fn set_instructions(&mut self, instructions: InstructionTemplates) -> Result<(), String> {
+        self.check_empty("instruction templates")?;
+        self.instructions = instructions;
+        Ok(())
+    }
+
+    /// What the embedder actually sees for `text`: the text pipeline, then
+    /// the instruction template for `role`.
+    fn prepare(&self, text: &str, role: EmbedRole) -> String {
+        self.instructions.apply(role, &self.text_pipeline.apply(text))
+    }
+
+    fn prepare_all(&self, texts: &[String], role: EmbedRole) -> Vec<String> {
+        texts.iter().map(|text| self.prepare(text, role)).collect()
+    }
+
+    /// Swaps in a fresh cache; a capacity of 0 turns caching off. Entries
+    /// already on disk under `disk_dir` are picked up again.
+    pub fn configure_embedding_cache(&mut self, capacity: usize, disk_dir: Option<String>) -> Result<(), String> {
+        self.embedding_cache = match capacity {
+            0 => None,
+            _ => Some(EmbeddingCache::new(capacity, disk_dir.map(PathBuf::from))?),
         };
+        Ok(())
     }
 
-    pub fn get_top_k_indexes(&self, query_string: String, k: usize) -> Vec<usize> {
-        let query = self.embedding_item.get_embedding(query_string.as_str());
-        let mut index_vec = vec![];
-        let mut dist_vec: Vec<f32> = vec![];
-        let mut idx = 0;
-        for node in self.data {
-            match node {
-                LeafNode(node) => {
-                    let node_item: Node<T> = node.into();
-                    for i in 0..node_item.get_index_len() {
-                        let dist = cosine_similarity_rust_float(&query, &node_item.indexes[i]);
-                        let loc = binary_search_floats(&dist_vec, &dist);
-                        index_vec.insert(loc, idx);
-                        dist_vec.insert(loc, dist);
-                        if dist_vec.len() > k {
-                            dist_vec.pop();
-                            index_vec.pop();
-                        }
-                        idx += 1;
+    pub fn embedding_cache_stats(&self) -> CacheStats {
+        self.embedding_cache.as_ref().map(|x| x.stats()).unwrap_or_default()
+    }
+
+    pub fn info(&self) -> CollectionInfo {
+        CollectionInfo {
+            config: self.config.clone(),
+            records: self.live_count(),
+            reembedding: self.reembedding.as_ref().map(|(embedded, total)| ReembedProgress {
+                embedded: embedded.load(Ordering::Relaxed),
+                total: *total,
+            }),
+            last_reembed: self.last_reembed.clone(),
+            instructions: self.instructions.clone(),
+        }
+    }
+
+    /// Snapshots the stored text of every live record for a re-embedding
+    /// job. Until `finish_reembed` the collection keeps serving the old vectors.
+    pub fn start_reembed(&mut self) -> Result<ReembedJob, String> {
+        if self.reembedding.is_some() {
+            return Err("A re-embedding job is already running".to_string());
+        }
+        if !self.multi_vectors.is_empty() {
+            return Err("Re-embedding collections with multi-vector records isn't supported".to_string());
+        }
+        let texts = self.live_texts(0)?;
+        let progress = Arc::new(AtomicUsize::new(0));
+        self.reembedding = Some((progress.clone(), texts.len()));
+        Ok(ReembedJob {
+            texts,
+            pipeline: self.text_pipeline.clone(),
+            instructions: self.instructions.clone(),
+            metric: self.config.metric,
+            snapshot_len: self.payloads.len(),
+            progress,
+        })
+    }
+
+    /// Embeds whatever was inserted while the job ran, then swaps every
+    /// vector, the embedder and the config in one go. Returns how many
+    /// records were re-embedded; `info` reports the outcome either way.
+    pub fn finish_reembed(&mut self, output: Result<ReembedOutput, String>) -> Result<usize, String> {
+        self.reembedding = None;
+        let result = output.and_then(|output| self.swap_in(output));
+        self.last_reembed = Some(match &result {
+            Ok(records) => ReembedOutcome::Finished { records: *records },
+            Err(e) => ReembedOutcome::Failed(e.clone()),
+        });
+        result
+    }
+
+    fn swap_in(&mut self, output: ReembedOutput) -> Result<usize, String> {
+        let mut vectors: HashMap<usize, Tensor> = output.vectors.into_iter().collect();
+        let late = self.live_texts(output.snapshot_len)?;
+        let texts: Vec<String> = late.iter().map(|(_, text)| self.prepare(text, EmbedRole::Document)).collect();
+        let embedded = embed_texts(output.embedder.as_ref(), &output.config, &texts, self.embedding_cache.as_ref());
+        for ((id, _), vector) in late.iter().zip(embedded) {
+            vectors.insert(*id, vector?);
+        }
+        // Records deleted while the job ran have no new vector; they are never read again.
+        let zero = Tensor::zeros(&[output.config.dims as i64], (tch::Kind::Float, Device::Cuda(0)));
+        let count = vectors.len();
+        for id in 0..self.payloads.len() {
+            let vector = vectors.remove(&id).unwrap_or_else(|| zero.copy());
+            *self.vector_at_mut(id) = vector;
+        }
+        self.embedding_item = output.embedder;
+        self.config = output.config;
+        Ok(count)
+    }
+
+    /// Vector and multi-vector inserts wait out a re-embedding job: swap-in
+    /// has no text to re-embed the first from and doesn't handle the second.
+    fn check_not_reembedding(&self) -> Result<(), String> {
+        match self.reembedding {
+            Some(_) => Err("Collection is being re-embedded; retry once GET /collection shows it finished".to_string()),
+            None => Ok(()),
+        }
+    }
+
+    /// Stored text of every live record from `start` on.
+    fn live_texts(&self, start: usize) -> Result<Vec<(usize, String)>, String> {
+        (start..self.payloads.len())
+            .filter(|id| self.live_payload(*id).is_some())
+            .map(|id| match self.source_texts.get(&id) {
+                Some(text) => Ok((id, text.clone())),
+                None => Err(format!("Record {} was inserted as a vector and has no text to re-embed", id)),
+            })
+            .collect()
+    }
+
+    /// Adds a named vector field. Existing records have no vector in it until
+    /// one is set with `set_named_vector`.
+    pub fn create_vector_space(&mut self, name: String, space: VectorSpace) -> Result<(), String> {
+        if name.is_empty() {
+            return Err("Vector space needs a name".to_string());
+        }
+        if self.vector_spaces.contains_key(&name) {
+            return Err(format!("Vector space {} already exists", name));
+        }
+        self.vector_spaces.insert(name, space);
+        Ok(())
+    }
+
+    pub fn set_named_vector(&mut self, request: &SetNamedVectorRequest) -> Result<(), String> {
+        if self.live_payload(request.id).is_none() {
+            return Err(format!("Record {} not found", request.id));
+        }
+        let space = self.vector_space(&request.name)?;
+        let vector = match (&request.vector, &request.text) {
+            (Some(vector), _) => space.tensor(vector)?,
+            (None, Some(text)) => space.embed(&self.prepare(text, EmbedRole::Document), self.embedding_cache.as_ref())?,
+            (None, None) => return Err("Need either text or a vector".to_string()),
+        };
+        self.set_named(request.id, vec![(request.name.clone(), vector)]);
+        Ok(())
+    }
+
+    fn bag_of_vectors(
+        &self,
+        text: Option<&str>,
+        vectors: Option<&[Vec<f32>]>,
+        role: EmbedRole,
+    ) -> Result<Vec<Tensor>, String> {
+        let bag = match (vectors, text) {
+            (Some(vectors), _) => vectors
+                .iter()
+                .map(|x| self.config.check_dims(x).map(|_| vec_to_tensor(x)))
+                .collect::<Result<Vec<Tensor>, String>>()?,
+            (None, Some(text)) => {
+                let sentences: Vec<String> = sentence_spans(text)
+                    .into_iter()
+                    .map(|(start, end)| text[start..end].to_string())
+                    .collect();
+                self.embed_batch(&sentences, role).into_iter().collect::<Result<Vec<Tensor>, String>>()?
+            }
+            (None, None) => return Err("Need either text or vectors".to_string()),
+        };
+        if bag.is_empty() {
+            return Err("No vectors to store or search with".to_string());
+        }
+        Ok(bag)
+    }
+
+    fn insert_tensor(&mut self, new_data: T, query: Tensor, payload: Payload) -> usize {
+        let id = self.payloads.len();
+        match self.data.last_mut() {
+            Some(LeafNode(node)) if node.get_index_len() < ELEMENTS_PER_PAGE => {
+                node.push_back(query, ChildType::Data(new_data));
+            }
+            _ => self.data.push(LeafNode(Node::create_new_with_data(query, ChildType::Data(new_data)))),
+        }
+        self.index_payload(id, &payload);
+        self.payloads.push(Some(payload));
+        id
+    }
+
+    pub fn update_payload(&mut self, id: usize, payload: Payload) -> bool {
+        let old = match self.payloads.get_mut(id) {
+            Some(Some(old)) => std::mem::replace(old, payload.clone()),
+            _ => return false,
+        };
+        self.unindex_payload(id, &old);
+        self.index_payload(id, &payload);
+        true
+    }
+
+    /// Tombstones the record: it stays in its page so later ids don't shift,
+    /// but it drops out of the payload indexes and every search.
+    pub fn delete(&mut self, id: usize) -> bool {
+        match self.payloads.get_mut(id).and_then(|x| x.take()) {
+            Some(payload) => {
+                self.unindex_payload(id, &payload);
+                self.text_index.remove(id);
+                self.sparse_index.remove(id);
+                self.multi_vectors.remove(id);
+                for space in self.vector_spaces.values_mut() {
+                    space.remove(id);
+                }
+                self.chunks.remove(&id);
+                self.source_texts.remove(&id);
+                if let Some(hash) = self.record_hashes.remove(&id) {
+                    if self.content_hashes.get(&hash) == Some(&id) {
+                        // Flagged copies of this text may still be live; the oldest takes over.
+                        let survivor = self.record_hashes.iter().filter(|(_, x)| **x == hash).map(|(id, _)| *id).min();
+                        match survivor {
+                            Some(survivor) => self.content_hashes.insert(hash, survivor),
+                            None => self.content_hashes.remove(&hash),
+                        };
                     }
                 }
-                _ => {}
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Attaches a sparse vector to a live record, replacing any it had.
+    pub fn set_sparse(&mut self, id: usize, sparse: &SparseVector) -> Result<(), String> {
+        if self.live_payload(id).is_none() {
+            return Err(format!("Record {} not found", id));
+        }
+        sparse.validate()?;
+        self.sparse_index.add(id, sparse);
+        Ok(())
+    }
+
+    pub fn create_payload_index(&mut self, key: String, index_type: PayloadIndexType) {
+        let mut index = PayloadIndex::new(index_type);
+        for (id, payload) in self.payloads.iter().enumerate() {
+            if let Some(value) = payload.as_ref().and_then(|x| x.get(&key)) {
+                index.add(id, value);
             }
         }
-        index_vec
+        self.payload_indexes.insert(key, index);
     }
 
-    pub fn get_indexes(&self, indices: Vec<usize>) -> Vec<String> {
-        let mut index_data: Vec<String> = Vec::new();
-        for index in indices {
-            let node_index = index / ELEMENTS_PER_PAGE;
-            let item_index = index % ELEMENTS_PER_PAGE;
-            let node = match self.data.get(node_index).unwrap() {
-                LeafNode(x) => {x}
-                _ => {panic!("Node invalid")}
+    /// All live records matching `filter`, answered from the payload indexes
+    /// when they cover it and by scanning payloads otherwise.
+    pub fn find_by_payload(&self, filter: &Filter) -> Vec<usize> {
+        let candidates: Vec<usize> = match self.indexed_candidates(filter) {
+            Some(ids) => ids.into_iter().collect(),
+            None => (0..self.payloads.len()).collect(),
+        };
+        candidates
+            .into_iter()
+            .filter(|id| self.live_payload(*id).is_some_and(|x| filter.matches(x)))
+            .collect()
+    }
+
+    /// Top-k with the score, data and payload of each hit.
+    pub fn search(&self, request: &SearchRequest, k: usize) -> Result<Vec<SearchHit<T>>, String> {
+        let filter = request.filter.as_ref();
+        if let Some(name) = &request.using {
+            if request.mmr.is_some() || request.hybrid.is_some() {
+                return Err("MMR and hybrid search only run on the default vector space".to_string());
+            }
+            let space = self.vector_space(name)?;
+            let query = space.embed(&self.prepare(&request.query, EmbedRole::Query), self.embedding_cache.as_ref())?;
+            let rank = |k: usize| self.space_top_k(space, &query, k, filter);
+            let ranked = if request.collapse_chunks {
+                self.collapse_chunks(k, rank)
+            } else {
+                rank(k)
             };
-            let new_data= match node.data.get(item_index) {
-                None => {panic!("Invalid Index Provided")}
-                Some(datum) => {datum.clone()}
+            return Ok(ranked
+                .into_iter()
+                .map(|(idx, score)| self.space_hit(space, idx, score, request.with_vector))
+                .collect());
+        }
+        let query = self.embed(&request.query, EmbedRole::Query)?;
+        let rank = |k: usize| match &request.hybrid {
+            Some(hybrid) => {
+                let fetch_k = hybrid.fetch_k.unwrap_or(k.saturating_mul(HYBRID_FETCH_FACTOR)).max(k);
+                let dense = self.top_k(&query, fetch_k, filter);
+                let keyword = self.text_index.search(&request.query, fetch_k, |id| {
+                    self.live_payload(id).is_some_and(|x| filter.is_none_or(|f| f.matches(x)))
+                });
+                let fused = fuse(&dense, &keyword, &hybrid.fusion);
+                match &request.mmr {
+                    Some(mmr) => self.mmr_rerank(fused, k, mmr.lambda),
+                    None => fused.into_iter().take(k).collect(),
+                }
+            }
+            None => self.ranked(&query, k, filter, request.mmr.as_ref()),
+        };
+        let ranked = if request.collapse_chunks {
+            self.collapse_chunks(k, rank)
+        } else {
+            rank(k)
+        };
+        Ok(ranked
+            .into_iter()
+            .map(|(idx, score)| self.hit(idx, score, request.with_vector))
+            .collect())
+    }
+
+    pub fn search_vector(&self, request: &VectorSearchRequest, k: usize) -> Result<Vec<SearchHit<T>>, String> {
+        let filter = request.filter.as_ref();
+        if let Some(name) = &request.using {
+            if request.mmr.is_some() {
+                return Err("MMR only runs on the default vector space".to_string());
+            }
+            let space = self.vector_space(name)?;
+            let query = space.tensor(&request.vector)?;
+            return Ok(self
+                .space_top_k(space, &query, k, filter)
+                .into_iter()
+                .map(|(idx, score)| self.space_hit(space, idx, score, request.with_vector))
+                .collect());
+        }
+        self.config.check_dims(&request.vector)?;
+        let query = vec_to_tensor(&request.vector);
+        Ok(self
+            .ranked(&query, k, filter, request.mmr.as_ref())
+            .into_iter()
+            .map(|(idx, score)| self.hit(idx, score, request.with_vector))
+            .collect())
+    }
+
+    /// Top-k within a named space.
+    fn space_top_k(&self, space: &VectorSpace, query: &Tensor, k: usize, filter: Option<&Filter>) -> Vec<(usize, f32)> {
+        let candidates: Box<dyn Iterator<Item = usize>> = match filter.and_then(|f| self.indexed_candidates(f)) {
+            Some(ids) => Box::new(ids.into_iter()),
+            None => Box::new(0..space.len()),
+        };
+        space.top_k(query, k, candidates, |id| {
+            self.live_payload(id).is_some_and(|x| filter.is_none_or(|f| f.matches(x)))
+        })
+    }
+
+    /// A hit from a named space, carrying that space's metric and vector.
+    fn space_hit(&self, space: &VectorSpace, idx: usize, score: f32, with_vector: bool) -> SearchHit<T> {
+        SearchHit {
+            metric: space.config.metric,
+            vector: with_vector.then(|| space.vector(idx).map(tensor_to_vec)).flatten(),
+            ..self.hit(idx, score, false)
+        }
+    }
+
+    /// Best hit per parent document, asking `rank` for more and more hits
+    /// until `k` distinct parents turn up or the records run out.
+    fn collapse_chunks(&self, k: usize, rank: impl Fn(usize) -> Vec<(usize, f32)>) -> Vec<(usize, f32)> {
+        let mut fetch = k;
+        loop {
+            let ranked = rank(fetch);
+            let mut parents = BTreeSet::new();
+            let collapsed: Vec<(usize, f32)> = ranked
+                .iter()
+                .copied()
+                .filter(|(idx, _)| parents.insert(self.chunks.get(idx).map_or(*idx, |x| x.parent)))
+                .take(k)
+                .collect();
+            if collapsed.len() == k || ranked.len() < fetch {
+                return collapsed;
+            }
+            fetch = fetch.saturating_mul(2);
+        }
+    }
+
+    fn ranked(&self, query: &Tensor, k: usize, filter: Option<&Filter>, mmr: Option<&MmrOptions>) -> Vec<(usize, f32)> {
+        match mmr {
+            Some(mmr) => {
+                let pool = self.top_k(query, mmr.fetch_k.unwrap_or(k.saturating_mul(MMR_FETCH_FACTOR)).max(k), filter);
+                self.mmr_rerank(pool, k, mmr.lambda)
+            }
+            None => self.top_k(query, k, filter),
+        }
+    }
+
+    /// Greedy MMR over `pool` (already sorted by relevance). Hits keep their
+    /// original relevance score; only the order and selection change.
+    fn mmr_rerank(&self, mut pool: Vec<(usize, f32)>, k: usize, lambda: f32) -> Vec<(usize, f32)> {
+        let mut selected: Vec<(usize, f32)> = Vec::with_capacity(k.min(pool.len()));
+        // Relevance can be a distance or a fused rank score, so it's rescaled
+        // to 0 (worst in the pool) through 1 (best) to weigh it against cosine.
+        let best = pool.first().map_or(0.0, |x| x.1);
+        let worst = pool.last().map_or(0.0, |x| x.1);
+        let mut relevance: Vec<f32> = pool
+            .iter()
+            .map(|(_, score)| if best == worst { 1.0 } else { (score - worst) / (best - worst) })
+            .collect();
+        // Highest similarity of each pool entry to anything selected so far.
+        let mut redundancy = vec![f32::NEG_INFINITY; pool.len()];
+        while selected.len() < k && !pool.is_empty() {
+            let (best, _) = relevance
+                .iter()
+                .zip(&redundancy)
+                .map(|(relevance, max_sim)| {
+                    let penalty = if selected.is_empty() { 0.0 } else { *max_sim };
+                    lambda * relevance - (1.0 - lambda) * penalty
+                })
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .unwrap();
+            let chosen = pool.remove(best);
+            relevance.remove(best);
+            redundancy.remove(best);
+            let chosen_vector = self.vector_at(chosen.0);
+            for ((idx, _), max_sim) in pool.iter().zip(redundancy.iter_mut()) {
+                let sim = cosine_similarity_rust_float(chosen_vector, self.vector_at(*idx));
+                *max_sim = max_sim.max(sim);
+            }
+            selected.push(chosen);
+        }
+        selected
+    }
+
+    /// Runs the embedder and checks its output against the collection's
+    /// dimension before anything touches the pages.
+    fn embed(&self, text: &str, role: EmbedRole) -> Result<Tensor, String> {
+        self.embed_batch(&[text.to_string()], role).pop().unwrap()
+    }
+
+    /// Calls `emit` for every live record within `request.threshold` of the
+    /// query, in page order, stopping after `request.limit` hits. Returns how
+    /// many were emitted.
+    pub fn range_search(&self, request: &RangeSearchRequest, mut emit: impl FnMut(SearchHit<T>)) -> Result<usize, String> {
+        let scorer = self.range_scorer(request)?;
+        let metric = scorer.metric(self.config.metric);
+        let filter = request.filter.as_ref();
+        let limit = request.limit.unwrap_or(usize::MAX);
+        // With no top-k to fill, an indexed candidate set is never worse than a full scan.
+        let candidates: Box<dyn Iterator<Item = usize>> = match filter.and_then(|f| self.indexed_candidates(f)) {
+            Some(ids) => Box::new(ids.into_iter()),
+            None => Box::new(0..self.payloads.len()),
+        };
+        let mut emitted = 0;
+        for idx in candidates {
+            if emitted >= limit {
+                break;
+            }
+            if !self.live_payload(idx).is_some_and(|x| filter.is_none_or(|f| f.matches(x))) {
+                continue;
+            }
+            let score = match &scorer {
+                RangeScorer::Dense(query) => Some(metric.score(query, self.vector_at(idx))),
+                RangeScorer::Space(space, query) => space.vector(idx).map(|x| metric.score(query, x)),
+                RangeScorer::Sparse(scores) => scores.get(&idx).copied(),
+                RangeScorer::MultiVector(query) => self.multi_vectors.max_sim(query, idx),
+            };
+            let Some(score) = score.filter(|x| metric.within(*x, request.threshold)) else {
+                continue;
             };
-            index_data.push(new_data);
+            let mut hit = self.hit(idx, score, request.with_vector);
+            hit.metric = metric;
+            if let RangeScorer::Space(space, _) = &scorer {
+                hit.vector = request.with_vector.then(|| space.vector(idx).map(tensor_to_vec)).flatten();
+            }
+            emit(hit);
+            emitted += 1;
         }
-        index_data
+        Ok(emitted)
     }
 
-    pub fn save(&self) {
-        println!("You need to implement save you idiot!");
+    /// Picks the index a range search measures against from the request.
+    fn range_scorer(&self, request: &RangeSearchRequest) -> Result<RangeScorer<'_>, String> {
+        let text = request.query.as_deref();
+        match (&request.using, &request.sparse, request.multi_vector) {
+            (None, None, false) => Ok(RangeScorer::Dense(self.query_tensor(text, request.vector.as_deref())?)),
+            (Some(name), None, false) => {
+                let space = self.vector_space(name)?;
+                let query = match (&request.vector, text) {
+                    (Some(vector), _) => space.tensor(vector)?,
+                    (None, Some(text)) => space.embed(&self.prepare(text, EmbedRole::Query), self.embedding_cache.as_ref())?,
+                    (None, None) => return Err("Query needs either text or a vector".to_string()),
+                };
+                Ok(RangeScorer::Space(space, query))
+            }
+            (None, Some(sparse), false) => {
+                // A fused dense+sparse score has no fixed scale to put a threshold on.
+                if text.is_some() || request.vector.is_some() {
+                    return Err("Sparse range search takes only the sparse vector".to_string());
+                }
+                sparse.validate()?;
+                Ok(RangeScorer::Sparse(self.sparse_index.scores(sparse)))
+            }
+            (None, None, true) => Ok(RangeScorer::MultiVector(self.bag_of_vectors(
+                text,
+                request.vectors.as_deref(),
+                EmbedRole::Query,
+            )?)),
+            _ => Err("Range search takes only one of using, sparse and multi_vector".to_string()),
+        }
     }
-}
 
-fn split_node<T>(mut node: Node<T>) -> TreeNode<T> {
-    if node.get_index_len() < ELEMENTS_PER_PAGE {
-        return LeafNode(node);
+    /// Top hits bucketed by the payload value under `request.group_by`: up to
+    /// `group_limit` groups of up to `group_size` hits each. Keeps widening
+    /// the candidate pool until the groups fill or the records run out.
+    pub fn search_groups(&self, request: &GroupSearchRequest) -> Result<Vec<HitGroup<T>>, String> {
+        if request.group_limit == 0 || request.group_size == 0 {
+            return Err("group_limit and group_size must be at least 1".to_string());
+        }
+        let query = self.query_tensor(request.query.as_deref(), request.vector.as_deref())?;
+        let filter = request.filter.as_ref();
+        let wanted = request.group_limit.saturating_mul(request.group_size);
+        let mut fetch = wanted;
+        loop {
+            let candidates = self.top_k(&query, fetch, filter);
+            let mut groups: Vec<(&PayloadValue, Vec<(usize, f32)>)> = vec![];
+            for (idx, score) in &candidates {
+                let value = match self.live_payload(*idx).and_then(|x| x.get(&request.group_by)) {
+                    Some(value) => value,
+                    None => continue,
+                };
+                match groups.iter().position(|(group, _)| *group == value) {
+                    Some(pos) if groups[pos].1.len() < request.group_size => groups[pos].1.push((*idx, *score)),
+                    Some(_) => {}
+                    None if groups.len() < request.group_limit => groups.push((value, vec![(*idx, *score)])),
+                    None => {}
+                }
+            }
+            let filled = groups.len() == request.group_limit
+                && groups.iter().all(|(_, hits)| hits.len() == request.group_size);
+            if filled || candidates.len() < fetch {
+                return Ok(groups
+                    .into_iter()
+                    .map(|(group, hits)| HitGroup {
+                        group: group.clone(),
+                        hits: hits
+                            .into_iter()
+                            .map(|(idx, score)| self.hit(idx, score, request.with_vector))
+                            .collect(),
+                    })
+                    .collect());
+            }
+            fetch = fetch.saturating_mul(2);
+        }
     }
-    let midpt = node.get_midpoint_idx();
-    node.reverse_data();
-    let (mut left, mut right) = (Node::new(), Node::new());
-    let mut selected: &mut Node<T> = &mut right;
-    while let Some((idx, datum)) = node.pop_last_data_and_index() {
-        if node.get_index_len() < midpt {
-            selected = &mut left;
+
+    /// Sparse dot-product search, fused with a dense search when the request
+    /// also carries a dense query.
+    pub fn search_sparse(&self, request: &SparseSearchRequest, k: usize) -> Result<Vec<SearchHit<T>>, String> {
+        request.sparse.validate()?;
+        let filter = request.filter.as_ref();
+        let keep = |id: usize| self.live_payload(id).is_some_and(|x| filter.is_none_or(|f| f.matches(x)));
+        let ranked = if request.query.is_none() && request.vector.is_none() {
+            self.sparse_index.search(&request.sparse, k, keep)
+        } else {
+            let query = self.query_tensor(request.query.as_deref(), request.vector.as_deref())?;
+            let fetch_k = request.fetch_k.unwrap_or(k.saturating_mul(HYBRID_FETCH_FACTOR)).max(k);
+            let dense = self.top_k(&query, fetch_k, filter);
+            let sparse = self.sparse_index.search(&request.sparse, fetch_k, keep);
+            let fusion = request.fusion.clone().unwrap_or(Fusion::Rrf { k: 60.0 });
+            fuse(&dense, &sparse, &fusion).into_iter().take(k).collect()
+        };
+        Ok(ranked
+            .into_iter()
+            .map(|(idx, score)| self.hit(idx, score, request.with_vector))
+            .collect())
+    }
+
+    /// Ranks multi-vector records by summed MaxSim against the query's bag.
+    /// Records inserted with a single vector never match.
+    pub fn search_multi_vector(&self, request: &MultiVectorSearchRequest, k: usize) -> Result<Vec<SearchHit<T>>, String> {
+        let query = self.bag_of_vectors(request.query.as_deref(), request.vectors.as_deref(), EmbedRole::Query)?;
+        let filter = request.filter.as_ref();
+        let per_vector = request.per_vector.unwrap_or(k.saturating_mul(MULTI_VECTOR_FETCH_FACTOR)).max(1);
+        Ok(self
+            .multi_vectors
+            .search(&query, k, per_vector, |id| {
+                self.live_payload(id).is_some_and(|x| filter.is_none_or(|f| f.matches(x)))
+            })
+            .into_iter()
+            .map(|(idx, score)| self.hit(idx, score, request.with_vector))
+            .collect())
+    }
+
+    fn query_tensor(&self, query: Option<&str>, vector: Option<&[f32]>) -> Result<Tensor, String> {
+        match (vector, query) {
+            (Some(vector), _) => {
+                self.config.check_dims(vector)?;
+                Ok(vec_to_tensor(vector))
+            }
+            (None, Some(text)) => self.embed(text, EmbedRole::Query),
+            (None, None) => Err("Query needs either text or a vector".to_string()),
         }
-        selected.push_back(idx, datum);
     }
-    let new_index = right.indexes.first().unwrap().copy();
-    TreeNode::OverflowNode(Box::new(LeafNode(left)), new_index, Box::new(LeafNode(right)))
-}
 
-fn insert_into_tree_node<T>(
-    node: TreeNode<T>,
-    new_data: T,
-    query: Tensor,
-    compare: impl Fn(&Tensor, &Tensor) -> bool,
-) -> TreeNode<T> {
-    match node {
-        LeafNode(mut node) => {
-            let loc = binary_search(&node.indexes, &query, &compare);
-            node.data.insert(loc, new_data);
-            node.indexes.insert(loc, query);
-            split_node(node)
-        }
-        Null => {
-            todo!();
-        }
-        TreeNode::OverflowNode(_, _, _) => {
-            panic!("Should never be inserting into an overflow node")
+    /// "More like this": averages the stored vectors of `positive`, pushes
+    /// away from the average of `negative`, and leaves the examples
+    /// themselves out of the results.
+    pub fn recommend(
+        &self,
+        positive: &[usize],
+        negative: &[usize],
+        k: usize,
+        filter: Option<&Filter>,
+        with_vector: bool,
+    ) -> Result<Vec<SearchHit<T>>, String> {
+        let positive_mean = self.mean_vector(positive)?.ok_or("Need at least one positive example".to_string())?;
+        let query = match self.mean_vector(negative)? {
+            Some(negative_mean) => &positive_mean + (&positive_mean - negative_mean),
+            None => positive_mean,
+        };
+        let excluded: BTreeSet<usize> = positive.iter().chain(negative).copied().collect();
+        Ok(self
+            .top_k(&query, k.saturating_add(excluded.len()), filter)
+            .into_iter()
+            .filter(|(idx, _)| !excluded.contains(idx))
+            .take(k)
+            .map(|(idx, score)| self.hit(idx, score, with_vector))
+            .collect())
+    }
+
+    fn mean_vector(&self, ids: &[usize]) -> Result<Option<Tensor>, String> {
+        let mut sum: Option<Tensor> = None;
+        for id in ids {
+            if self.live_payload(*id).is_none() {
+                return Err(format!("Record {} not found", id));
+            }
+            let vector = self.vector_at(*id);
+            sum = Some(match sum {
+                Some(acc) => acc + vector,
+                None => vector.copy(),
+            });
+        }
+        Ok(sum.map(|x| x / ids.len() as f64))
+    }
+
+    /// One embedder call for all of `texts`; results line up with `texts`.
+    fn embed_batch(&self, texts: &[String], role: EmbedRole) -> Vec<Result<Tensor, String>> {
+        embed_texts(
+            self.embedding_item.as_ref(),
+            &self.config,
+            &self.prepare_all(texts, role),
+            self.embedding_cache.as_ref(),
+        )
+    }
+
+    /// Runs many queries at once: text queries share one embedder call and
+    /// every query that would scan the pages shares a single pass over them.
+    pub fn search_batch(
+        &self,
+        queries: Vec<BatchQuery>,
+        default_k: usize,
+        default_filter: Option<&Filter>,
+        with_vector: bool,
+    ) -> Vec<Result<Vec<SearchHit<T>>, String>> {
+        let texts: Vec<String> = queries
+            .iter()
+            .filter(|q| q.vector.is_none())
+            .filter_map(|q| q.query.clone())
+            .collect();
+        let mut embedded = self.embed_batch(&texts, EmbedRole::Query).into_iter();
+        let tensors: Vec<Result<Tensor, String>> = queries
+            .iter()
+            .map(|q| match (&q.vector, &q.query) {
+                (Some(vector), _) => self.config.check_dims(vector).map(|_| vec_to_tensor(vector)),
+                (None, Some(_)) => embedded.next().unwrap(),
+                (None, None) => Err("Query needs either text or a vector".to_string()),
+            })
+            .collect();
+
+        let mut results: Vec<Result<Vec<(usize, f32)>, String>> = Vec::with_capacity(queries.len());
+        let mut shared = vec![];
+        let mut shared_positions = vec![];
+        for (position, (q, tensor)) in queries.iter().zip(&tensors).enumerate() {
+            let k = q.k.unwrap_or(default_k);
+            let filter = q.filter.as_ref().or(default_filter);
+            match tensor {
+                Err(e) => results.push(Err(e.clone())),
+                Ok(query) => match self.explain(filter, k).strategy {
+                    SearchStrategy::FilteredScan => {
+                        shared.push((query, k, filter));
+                        shared_positions.push(position);
+                        results.push(Ok(vec![]));
+                    }
+                    _ => results.push(Ok(self.top_k(query, k, filter))),
+                },
+            }
+        }
+        for (position, top) in shared_positions.into_iter().zip(self.scan_top_k_multi(&shared)) {
+            results[position] = Ok(top);
+        }
+        results
+            .into_iter()
+            .map(|result| {
+                result.map(|top| {
+                    top.into_iter()
+                        .map(|(idx, score)| self.hit(idx, score, with_vector))
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    /// Reports which strategy `top_k` will use for this filter and why.
+    pub fn explain(&self, filter: Option<&Filter>, k: usize) -> QueryPlan {
+        let total_records = self.payloads.iter().filter(|x| x.is_some()).count();
+        let estimated_matches = filter.and_then(|f| self.estimate_matches(f));
+        plan_search(filter.is_some(), estimated_matches, total_records, k)
+    }
+
+    fn top_k(&self, query: &Tensor, k: usize, filter: Option<&Filter>) -> Vec<(usize, f32)> {
+        match (self.explain(filter, k).strategy, filter) {
+            (SearchStrategy::BruteForce, Some(filter)) => {
+                let mut index_vec = vec![];
+                let mut dist_vec: Vec<f32> = vec![];
+                for idx in self.indexed_candidates(filter).unwrap_or_default() {
+                    if self.live_payload(idx).is_some_and(|x| filter.matches(x)) {
+                        let dist = cosine_similarity_rust_float(query, self.vector_at(idx));
+                        insert_top_k(&mut index_vec, &mut dist_vec, idx, dist, k);
+                    }
+                }
+                index_vec.into_iter().zip(dist_vec).collect()
+            }
+            (SearchStrategy::PostFilter { oversample }, Some(filter)) => {
+                let unfiltered = self.scan_top_k(query, k.saturating_mul(oversample), None);
+                let hits: Vec<(usize, f32)> = unfiltered
+                    .iter()
+                    .copied()
+                    .filter(|(idx, _)| self.live_payload(*idx).is_some_and(|x| filter.matches(x)))
+                    .take(k)
+                    .collect();
+                // Oversampling came up short, so the estimate was off: redo it properly.
+                if hits.len() < k && unfiltered.len() == k.saturating_mul(oversample) {
+                    return self.scan_top_k(query, k, Some(filter));
+                }
+                hits
+            }
+            (_, filter) => self.scan_top_k(query, k, filter),
+        }
+    }
+
+    fn scan_top_k(&self, query: &Tensor, k: usize, filter: Option<&Filter>) -> Vec<(usize, f32)> {
+        self.scan_top_k_multi(&[(query, k, filter)]).pop().unwrap()
+    }
+
+    /// Walks the pages once, scoring every record against each query.
+    fn scan_top_k_multi(&self, queries: &[(&Tensor, usize, Option<&Filter>)]) -> Vec<Vec<(usize, f32)>> {
+        let mut tops: Vec<(Vec<usize>, Vec<f32>)> = vec![(vec![], vec![]); queries.len()];
+        let mut idx = 0;
+        for LeafNode(node_item) in &self.data {
+            for i in 0..node_item.get_index_len() {
+                if let Some(payload) = self.live_payload(idx) {
+                    for ((query, k, filter), (index_vec, dist_vec)) in queries.iter().zip(tops.iter_mut()) {
+                        if filter.is_none_or(|f| f.matches(payload)) {
+                            let dist = cosine_similarity_rust_float(query, &node_item.indexes[i]);
+                            insert_top_k(index_vec, dist_vec, idx, dist, *k);
+                        }
+                    }
+                }
+                idx += 1;
+            }
+        }
+        tops.into_iter()
+            .map(|(index_vec, dist_vec)| index_vec.into_iter().zip(dist_vec).collect())
+            .collect()
+    }
+
+    fn hit(&self, id: usize, score: f32, with_vector: bool) -> SearchHit<T> {
+        SearchHit {
+            id,
+            score,
+            metric: self.config.metric,
+            data: self.data_at(id).clone(),
+            payload: self.live_payload(id).cloned().unwrap_or_default(),
+            vector: with_vector.then(|| tensor_to_vec(self.vector_at(id))),
+            chunk: self.chunks.get(&id).copied(),
         }
     }
+
+    fn data_at(&self, id: usize) -> &T {
+        let LeafNode(node) = &self.data[id / ELEMENTS_PER_PAGE];
+        match node.data.get(id % ELEMENTS_PER_PAGE) {
+            None => {panic!("Invalid Index Provided")}
+            Some(datum) => {datum}
+        }
+    }
+
+    fn live_payload(&self, id: usize) -> Option<&Payload> {
+        self.payloads.get(id).and_then(|x| x.as_ref())
+    }
+
+    fn vector_at(&self, id: usize) -> &Tensor {
+        let LeafNode(node) = &self.data[id / ELEMENTS_PER_PAGE];
+        &node.indexes[id % ELEMENTS_PER_PAGE]
+    }
+
+    fn vector_at_mut(&mut self, id: usize) -> &mut Tensor {
+        let LeafNode(node) = &mut self.data[id / ELEMENTS_PER_PAGE];
+        &mut node.indexes[id % ELEMENTS_PER_PAGE]
+    }
+
+    fn index_payload(&mut self, id: usize, payload: &Payload) {
+        for (key, index) in self.payload_indexes.iter_mut() {
+            if let Some(value) = payload.get(key) {
+                index.add(id, value);
+            }
+        }
+    }
+
+    fn unindex_payload(&mut self, id: usize, payload: &Payload) {
+        for (key, index) in self.payload_indexes.iter_mut() {
+            if let Some(value) = payload.get(key) {
+                index.remove(id, value);
+            }
+        }
+    }
+
+    /// Candidate ids from whichever parts of `filter` have a payload index,
+    /// or None when no index applies and the caller has to scan.
+    fn indexed_candidates(&self, filter: &Filter) -> Option<BTreeSet<usize>> {
+        match filter {
+            Filter::And(filters) => filters
+                .iter()
+                .filter_map(|f| self.indexed_candidates(f))
+                .reduce(|acc, ids| acc.intersection(&ids).copied().collect()),
+            _ => self.payload_indexes.get(filter.key()?)?.lookup(filter),
+        }
+    }
+
+    fn estimate_matches(&self, filter: &Filter) -> Option<usize> {
+        match filter {
+            Filter::And(filters) => filters.iter().filter_map(|f| self.estimate_matches(f)).min(),
+            _ => self.payload_indexes.get(filter.key()?)?.estimate(filter),
+        }
+    }
+
+    pub fn save(&self) {
+        println!("You need to implement save you idiot!");
+    }
 }
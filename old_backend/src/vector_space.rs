@@ -0,0 +1,162 @@
+use crate::collection::CollectionConfig;
+use crate::embedder::Embedder;
+use crate::embedding_cache::EmbeddingCache;
+use crate::helpers::{insert_top_k, vec_to_tensor};
+use tch::Tensor;
+
+/// A named vector field on a collection: its own dimension, metric, storage
+/// and optionally its own embedder, with record ids shared with the default
+/// space in `VectorDB`.
+pub(crate) struct VectorSpace {
+    pub config: CollectionConfig,
+    embedder: Option<Box<dyn Embedder + Send>>,
+    /// Indexed by record id; None where a record has no vector in this space.
+    vectors: Vec<Option<Tensor>>,
+}
+
+impl VectorSpace {
+    pub fn new(mut config: CollectionConfig, embedder: Option<Box<dyn Embedder + Send>>) -> Result<Self, String> {
+        if let Some(embedder) = &embedder {
+            config.model_id = Some(embedder.model_id());
+            if embedder.dims() != config.dims {
+                return Err(format!(
+                    "Vector space is configured for {} dimensions but the embedder produces {}",
+                    config.dims,
+                    embedder.dims()
+                ));
+            }
+        }
+        Ok(Self { config, embedder, vectors: vec![] })
+    }
+
+    pub fn has_embedder(&self) -> bool {
+        self.embedder.is_some()
+    }
+
+    pub fn embed(&self, text: &str, cache: Option<&EmbeddingCache>) -> Result<Tensor, String> {
+        match &self.embedder {
+            Some(embedder) => embed_texts(embedder.as_ref(), &self.config, &[text.to_string()], cache).pop().unwrap(),
+            None => Err("Vector space has no embedder, pass a vector instead".to_string()),
+        }
+    }
+
+    pub fn embed_batch(&self, texts: &[String], cache: Option<&EmbeddingCache>) -> Vec<Result<Tensor, String>> {
+        match &self.embedder {
+            Some(embedder) => embed_texts(embedder.as_ref(), &self.config, texts, cache),
+            None => texts
+                .iter()
+                .map(|_| Err("Vector space has no embedder, pass a vector instead".to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn tensor(&self, vector: &[f32]) -> Result<Tensor, String> {
+        self.config.check_dims(vector)?;
+        Ok(vec_to_tensor(vector))
+    }
+
+    pub fn set(&mut self, id: usize, vector: Tensor) {
+        if self.vectors.len() <= id {
+            self.vectors.resize_with(id + 1, || None);
+        }
+        self.vectors[id] = Some(vector);
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        if let Some(x) = self.vectors.get_mut(id) {
+            *x = None;
+        }
+    }
+
+    pub fn vector(&self, id: usize) -> Option<&Tensor> {
+        self.vectors.get(id).and_then(|x| x.as_ref())
+    }
+
+    /// Best `k` of `candidates` that have a vector here and pass `keep`,
+    /// best first under this space's metric.
+    pub fn top_k(
+        &self,
+        query: &Tensor,
+        k: usize,
+        candidates: impl Iterator<Item = usize>,
+        keep: impl Fn(usize) -> bool,
+    ) -> Vec<(usize, f32)> {
+        let metric = self.config.metric;
+        let mut index_vec = vec![];
+        let mut rank_vec = vec![];
+        for id in candidates {
+            if let Some(vector) = self.vector(id) {
+                if keep(id) {
+                    insert_top_k(&mut index_vec, &mut rank_vec, id, metric.rank_key(metric.score(query, vector)), k);
+                }
+            }
+        }
+        index_vec
+            .into_iter()
+            .zip(rank_vec)
+            .map(|(id, key)| (id, metric.rank_key(key)))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+}
+
+/// One embedder call for whatever in `texts` isn't already in `cache`,
+/// checked against `config`; results line up with `texts`.
+pub(crate) fn embed_texts(
+    embedder: &dyn Embedder,
+    config: &CollectionConfig,
+    texts: &[String],
+    cache: Option<&EmbeddingCache>,
+) -> Vec<Result<Tensor, String>> {
+    let model_id = embedder.model_id();
+    if let Err(e) = config.check_model(Some(&model_id)) {
+        return texts.iter().map(|_| Err(e.clone())).collect();
+    }
+    let keys: Vec<Option<[u8; 32]>> = texts
+        .iter()
+        .map(|text| cache.map(|_| EmbeddingCache::key(&model_id, text)))
+        .collect();
+    let cached: Vec<Option<Vec<f32>>> = keys
+        .iter()
+        .map(|key| cache.zip(key.as_ref()).and_then(|(cache, key)| cache.get(key)))
+        .collect();
+    // The FFI layer can't take interior NULs, so those fail here instead of panicking.
+    let missing: Vec<String> = texts
+        .iter()
+        .zip(&cached)
+        .filter(|(text, cached)| cached.is_none() && !text.contains('\0'))
+        .map(|(text, _)| text.clone())
+        .collect();
+    let mut vectors = if missing.is_empty() { vec![] } else { embedder.get_embeddings(&missing) }.into_iter();
+    texts
+        .iter()
+        .zip(cached)
+        .zip(keys)
+        .map(|((text, cached), key)| {
+            let vector = match cached {
+                Some(vector) => {
+                    // Disk entries could have been written by anything.
+                    config.check_dims(&vector)?;
+                    vector
+                }
+                None => {
+                    if text.contains('\0') {
+                        return Err("Text contains a NUL byte".to_string());
+                    }
+                    let vector = vectors
+                        .next()
+                        .ok_or("Embedder returned fewer vectors than texts".to_string())??;
+                    config.check_dims(&vector)?;
+                    if let Some((cache, key)) = cache.zip(key) {
+                        cache.put(key, &vector);
+                    }
+                    vector
+                }
+            };
+            Ok(vec_to_tensor(&vector))
+        })
+        .collect()
+}
@@ -0,0 +1,40 @@
+use std::fs;
+use half::{bf16, f16};
+use safetensors::{Dtype, SafeTensors};
+
+/// A safetensors file read fully into memory; tensors are pulled out of it
+/// by name and widened to f32.
+pub(crate) struct Weights {
+    bytes: Vec<u8>,
+}
+
+impl Weights {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Can't read weights {}: {}", path, e))?;
+        SafeTensors::deserialize(&bytes).map_err(|e| format!("Invalid safetensors file {}: {}", path, e))?;
+        Ok(Self { bytes })
+    }
+
+    /// Row-major values and shape of tensor `name`.
+    pub fn tensor(&self, name: &str) -> Result<(Vec<f32>, Vec<usize>), String> {
+        let tensors = SafeTensors::deserialize(&self.bytes).map_err(|e| e.to_string())?;
+        let view = tensors.tensor(name).map_err(|_| format!("Weights have no tensor {}", name))?;
+        let data = view.data();
+        let values = match view.dtype() {
+            Dtype::F32 => data.chunks_exact(4).map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect(),
+            Dtype::F16 => data.chunks_exact(2).map(|x| f16::from_le_bytes([x[0], x[1]]).to_f32()).collect(),
+            Dtype::BF16 => data.chunks_exact(2).map(|x| bf16::from_le_bytes([x[0], x[1]]).to_f32()).collect(),
+            dtype => return Err(format!("Tensor {} is {:?}, expected a float type", name, dtype)),
+        };
+        Ok((values, view.shape().to_vec()))
+    }
+
+    /// Like `tensor` but insists on the given shape.
+    pub fn tensor_shaped(&self, name: &str, shape: &[usize]) -> Result<Vec<f32>, String> {
+        let (values, found) = self.tensor(name)?;
+        if found != shape {
+            return Err(format!("Tensor {} has shape {:?}, expected {:?}", name, found, shape));
+        }
+        Ok(values)
+    }
+}